@@ -0,0 +1,86 @@
+use nih_plug::prelude::Enum;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ChannelMode {
+    Stereo,
+    Left,
+    Right,
+    Mid,
+    Side,
+    DualLeftRight,
+    DualMidSide,
+}
+
+impl Enum for ChannelMode {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Stereo Linked",
+            "Left Only",
+            "Right Only",
+            "Mid Only",
+            "Side Only",
+            "Dual L/R",
+            "Dual M/S",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "stereo",
+            "left",
+            "right",
+            "mid",
+            "side",
+            "dual_lr",
+            "dual_ms",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            ChannelMode::Stereo => 0,
+            ChannelMode::Left => 1,
+            ChannelMode::Right => 2,
+            ChannelMode::Mid => 3,
+            ChannelMode::Side => 4,
+            ChannelMode::DualLeftRight => 5,
+            ChannelMode::DualMidSide => 6,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => ChannelMode::Stereo,
+            1 => ChannelMode::Left,
+            2 => ChannelMode::Right,
+            3 => ChannelMode::Mid,
+            4 => ChannelMode::Side,
+            5 => ChannelMode::DualLeftRight,
+            6 => ChannelMode::DualMidSide,
+            _ => panic!("Invalid channel mode index."),
+        }
+    }
+}
+
+impl ChannelMode {
+    // modes that filter the mid/side pair instead of the left/right pair
+    pub fn is_mid_side(self) -> bool {
+        matches!(self, ChannelMode::Mid | ChannelMode::Side | ChannelMode::DualMidSide)
+    }
+
+    // modes where the second channel gets its own cutoff and gain
+    pub fn is_dual(self) -> bool {
+        matches!(self, ChannelMode::DualLeftRight | ChannelMode::DualMidSide)
+    }
+}
+
+// same formulas as in the MSLR plugin (MidSideMixer), with r_side = -l_side
+pub fn mid_side_encode(left: f32, right: f32) -> (f32, f32) {
+    let mid = (left + right) * 0.5;
+    let side = (left - right) * 0.5;
+    (mid, side)
+}
+
+pub fn mid_side_decode(mid: f32, side: f32) -> (f32, f32) {
+    (mid + side, mid - side)
+}
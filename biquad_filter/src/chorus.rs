@@ -1,4 +1,5 @@
 use std::collections::VecDeque;
+use std::f32::consts::PI;
 
 use rand::distributions::uniform::SampleRange;
 
@@ -33,8 +34,10 @@ impl Chorus {
         for i in 0..5 {
             left_delays.push(Delay::new(sample_rate as usize, delay_samples, 0.0));
             right_delays.push(Delay::new(sample_rate as usize, delay_samples, 0.0));
-            left_lfos.push(lfo::LFO::new_random_phase(sample_rate, rate));
-            right_lfos.push(lfo::LFO::new_random_phase(sample_rate, rate));
+            // voices spread evenly over the cycle, the right channel half a step after the left
+            let phase = 2.0 * PI * i as f32 / 5.0;
+            left_lfos.push(lfo::LFO::new_with_phase(sample_rate, rate, phase));
+            right_lfos.push(lfo::LFO::new_with_phase(sample_rate, rate, phase + PI / 5.0));
         }
 
         let mut left_feedback_buffer: Box<VecDeque<f32>> 
@@ -18,7 +18,7 @@ struct Data {
 impl Model for Data {}
 
 pub(crate) fn default_state() -> Arc<ViziaState> {
-    ViziaState::new(|| (400, 200))
+    ViziaState::new(|| (760, 570))
 }
 
 pub(crate) fn create(
@@ -50,6 +50,9 @@ pub(crate) fn create(
                     VStack::new(cx, |cx| {
                         Label::new(cx, "filter type").font_size(15.0)
                         .height(Pixels(30.0));
+
+                        Label::new(cx, "design").font_size(15.0)
+                        .height(Pixels(30.0));
     
                         Label::new(cx, "cutoff").font_size(15.0)
                         .height(Pixels(30.0));
@@ -59,12 +62,48 @@ pub(crate) fn create(
     
                         Label::new(cx, "gain").font_size(15.0)
                         .height(Pixels(30.0));
+
+                        Label::new(cx, "morph").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "vowel").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "vowel lfo rate").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "vowel lfo depth").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "channels").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "r/s cutoff").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "r/s gain").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "saturation").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "drive").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "resonance drive").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "auto gain").font_size(15.0)
+                        .height(Pixels(30.0));
     
                     }).child_top(Pixels(6.0));
     
                     VStack::new(cx, |cx| {
                         ParamSlider::new(cx, Data::filter_data, |params| &params.filter_type)
                         .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.design)
+                        .height(Pixels(30.0));
                     
                         ParamSlider::new(cx, Data::filter_data, |params| &params.cutoff)
                         .height(Pixels(30.0));
@@ -74,6 +113,86 @@ pub(crate) fn create(
 
                         ParamSlider::new(cx, Data::filter_data, |params| &params.gain)
                         .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.morph)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.vowel)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.vowel_lfo_rate)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.vowel_lfo_depth)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.channel_mode)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.cutoff_rs)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.gain_rs)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.saturation)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.drive)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.drive_feedback)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.auto_gain)
+                        .height(Pixels(30.0));
+                    });
+
+                    VStack::new(cx, |cx| {
+                        Label::new(cx, "routing").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "balance").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "filter b type").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "filter b cutoff").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "filter b resonance").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "filter b gain").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                        Label::new(cx, "linear phase").font_size(15.0)
+                        .height(Pixels(30.0));
+
+                    }).child_top(Pixels(6.0));
+
+                    VStack::new(cx, |cx| {
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.routing)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.balance)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.filter_type_b)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.cutoff_b)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.resonance_b)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.gain_b)
+                        .height(Pixels(30.0));
+
+                        ParamSlider::new(cx, Data::filter_data, |params| &params.linear_phase)
+                        .height(Pixels(30.0));
                     });
                 }).col_between(Pixels(30.0));
                 
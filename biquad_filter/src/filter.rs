@@ -1,7 +1,11 @@
 use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
 
 use nih_plug::prelude::Enum;
 
+use crate::matched;
+use crate::saturation::{self, SaturationType};
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum FilterType {
     LowPass1,
@@ -15,6 +19,14 @@ pub enum FilterType {
     LowShelf,
     HighShelf,
     Peak,
+    Morph,
+    LadderLowPass12,
+    LadderLowPass24,
+    LadderBandPass12,
+    LadderBandPass24,
+    LadderHighPass12,
+    LadderHighPass24,
+    Formant,
 }
 
 impl Enum for FilterType {
@@ -31,6 +43,14 @@ impl Enum for FilterType {
             "LowShelf",
             "HighShelf",
             "Peak",
+            "Morph (LP-BP-HP-Notch)",
+            "Ladder Low-Pass 12dB",
+            "Ladder Low-Pass 24dB",
+            "Ladder Band-Pass 12dB",
+            "Ladder Band-Pass 24dB",
+            "Ladder High-Pass 12dB",
+            "Ladder High-Pass 24dB",
+            "Formant (Vowel)",
         ]
     }
 
@@ -47,6 +67,14 @@ impl Enum for FilterType {
             "ls",
             "hs",
             "peak",
+            "morph",
+            "ladder_lp12",
+            "ladder_lp24",
+            "ladder_bp12",
+            "ladder_bp24",
+            "ladder_hp12",
+            "ladder_hp24",
+            "formant",
         ])
     }
 
@@ -63,6 +91,14 @@ impl Enum for FilterType {
             FilterType::LowShelf => 8,
             FilterType::HighShelf => 9,
             FilterType::Peak => 10,
+            FilterType::Morph => 11,
+            FilterType::LadderLowPass12 => 12,
+            FilterType::LadderLowPass24 => 13,
+            FilterType::LadderBandPass12 => 14,
+            FilterType::LadderBandPass24 => 15,
+            FilterType::LadderHighPass12 => 16,
+            FilterType::LadderHighPass24 => 17,
+            FilterType::Formant => 18,
         }
     }
 
@@ -79,12 +115,71 @@ impl Enum for FilterType {
             8 => FilterType::LowShelf,
             9 => FilterType::HighShelf,
             10 => FilterType::Peak,
+            11 => FilterType::Morph,
+            12 => FilterType::LadderLowPass12,
+            13 => FilterType::LadderLowPass24,
+            14 => FilterType::LadderBandPass12,
+            15 => FilterType::LadderBandPass24,
+            16 => FilterType::LadderHighPass12,
+            17 => FilterType::LadderHighPass24,
+            18 => FilterType::Formant,
             _ => panic!("Invalid filter type index."),
         }
     }
 
 }
 
+impl FilterType {
+    pub fn is_ladder(self) -> bool {
+        matches!(
+            self,
+            FilterType::LadderLowPass12
+                | FilterType::LadderLowPass24
+                | FilterType::LadderBandPass12
+                | FilterType::LadderBandPass24
+                | FilterType::LadderHighPass12
+                | FilterType::LadderHighPass24
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FilterDesign {
+    Bilinear,
+    Matched,
+}
+
+impl Enum for FilterDesign {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Bilinear",
+            "Matched Magnitude",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "bilinear",
+            "matched",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            FilterDesign::Bilinear => 0,
+            FilterDesign::Matched => 1,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => FilterDesign::Bilinear,
+            1 => FilterDesign::Matched,
+            _ => panic!("Invalid filter design index."),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct BiquadCoefficients {
     a0: f32,
@@ -110,6 +205,22 @@ impl BiquadCoefficients {
     }
 }
 
+#[cfg(test)]
+impl BiquadCoefficients {
+    // magnitude response at w radians per sample
+    pub fn magnitude(&self, w: f64) -> f64 {
+        let response = |c0: f64, c1: f64, c2: f64| {
+            (c0 + c1 * w.cos() + c2 * (2.0 * w).cos(), -(c1 * w.sin() + c2 * (2.0 * w).sin()))
+        };
+        let (num_re, num_im) = response(self.a0 as f64, self.a1 as f64, self.a2 as f64);
+        let (den_re, den_im) = response(1.0, self.b0 as f64, self.b1 as f64);
+        let den = den_re * den_re + den_im * den_im;
+        let re = self.c0 as f64 * (num_re * den_re + num_im * den_im) / den + self.d0 as f64;
+        let im = self.c0 as f64 * (num_im * den_re - num_re * den_im) / den;
+        (re * re + im * im).sqrt()
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct BiquadFilter {
     coeffs: BiquadCoefficients,
@@ -126,6 +237,9 @@ pub struct BiquadFilter {
     r_y2: f32,
 
     sample_rate: f32,
+    // saturation applied to the output where it is fed back, limits the resonance
+    feedback_saturation: SaturationType,
+    design: FilterDesign,
 }
 
 impl BiquadFilter {
@@ -142,6 +256,8 @@ impl BiquadFilter {
             r_y1: 0.0,
             r_y2: 0.0,
             sample_rate: 44100.0,
+            feedback_saturation: SaturationType::Off,
+            design: FilterDesign::Bilinear,
         }
     }
 
@@ -149,6 +265,14 @@ impl BiquadFilter {
         self.sample_rate = sample_rate;
     }
 
+    pub fn set_feedback_saturation(&mut self, saturation_type: SaturationType) {
+        self.feedback_saturation = saturation_type;
+    }
+
+    pub fn set_design(&mut self, design: FilterDesign) {
+        self.design = design;
+    }
+
     pub fn reset_filter(&mut self) {
         self.l_x1 = 0.0;
         self.l_x2 = 0.0;
@@ -172,8 +296,9 @@ impl BiquadFilter {
         self.l_x2 = self.l_x1;
         self.l_x1 = x;
         
+        // only the recursion is driven, the output itself is left unclipped
         self.l_y2 = self.l_y1;
-        self.l_y1 = y;
+        self.l_y1 = saturation::saturate(self.feedback_saturation, y);
 
         let y = self.coeffs.c0 * y + self.coeffs.d0 * x;
 
@@ -192,7 +317,7 @@ impl BiquadFilter {
         self.r_x1 = x;
         
         self.r_y2 = self.r_y1;
-        self.r_y1 = y;
+        self.r_y1 = saturation::saturate(self.feedback_saturation, y);
 
         let y = self.coeffs.c0 * y + self.coeffs.d0 * x;
 
@@ -385,7 +510,16 @@ impl BiquadFilter {
         }
     }
 
+    // gain is linear like the plugin's gain parameters, the designs take it in dB
     pub fn coefficients(&mut self, filter_type: FilterType, cutoff: f32, q: f32, gain: f32) {
+        let gain = 20.0 * gain.log10();
+        if self.design == FilterDesign::Matched {
+            if let Some(coeffs) = matched::coefficients(filter_type, self.sample_rate, cutoff, q, gain) {
+                self.coeffs = coeffs;
+                return;
+            }
+        }
+
         match filter_type {
             FilterType::LowPass1 => {
                 self.first_order_lpf_coefficients(self.sample_rate, cutoff);
@@ -420,6 +554,271 @@ impl BiquadFilter {
             FilterType::Peak => {
                 self.peak_coefficients(self.sample_rate, cutoff, q, gain);
             }
+            // not single biquads, processed by the other filter structures in FilterSection
+            _ => {}
         }
     }
-}
\ No newline at end of file
+}
+
+// sample type of TdfBiquadFilter, f64 keeps low cutoffs at high sample rates accurate
+pub trait FilterPrecision: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn from_f64(x: f64) -> Self;
+    fn from_f32(x: f32) -> Self;
+    fn to_f32(self) -> f32;
+}
+
+impl FilterPrecision for f32 {
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl FilterPrecision for f64 {
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn from_f32(x: f32) -> Self {
+        x as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+// transposed direct form II biquad. only two state values per channel, and with f64
+// state and coefficients the poles of very low cutoffs stay where they should be.
+// coefficients are always calculated in f64
+#[derive(Clone, Copy)]
+pub struct TdfBiquadFilter<T: FilterPrecision> {
+    a0: T,
+    a1: T,
+    a2: T,
+    b0: T,
+    b1: T,
+
+    l_s1: T,
+    l_s2: T,
+    r_s1: T,
+    r_s2: T,
+
+    sample_rate: f32,
+}
+
+impl<T: FilterPrecision> TdfBiquadFilter<T> {
+    pub fn new() -> Self {
+        let zero = T::from_f64(0.0);
+        Self {
+            a0: T::from_f64(1.0),
+            a1: zero,
+            a2: zero,
+            b0: zero,
+            b1: zero,
+            l_s1: zero,
+            l_s2: zero,
+            r_s1: zero,
+            r_s2: zero,
+            sample_rate: 44100.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn reset_filter(&mut self) {
+        let zero = T::from_f64(0.0);
+        self.l_s1 = zero;
+        self.l_s2 = zero;
+        self.r_s1 = zero;
+        self.r_s2 = zero;
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let x = T::from_f32(x);
+        let y = self.a0 * x + self.l_s1;
+        self.l_s1 = self.a1 * x - self.b0 * y + self.l_s2;
+        self.l_s2 = self.a2 * x - self.b1 * y;
+        y.to_f32()
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let x = T::from_f32(x);
+        let y = self.a0 * x + self.r_s1;
+        self.r_s1 = self.a1 * x - self.b0 * y + self.r_s2;
+        self.r_s2 = self.a2 * x - self.b1 * y;
+        y.to_f32()
+    }
+
+    fn set_coefficients(&mut self, a0: f64, a1: f64, a2: f64, b0: f64, b1: f64) {
+        self.a0 = T::from_f64(a0);
+        self.a1 = T::from_f64(a1);
+        self.a2 = T::from_f64(a2);
+        self.b0 = T::from_f64(b0);
+        self.b1 = T::from_f64(b1);
+    }
+
+    // same designs as BiquadFilter
+    pub fn second_order_lpf_coefficients(&mut self, cutoff: f32, q: f32) {
+        let o = 2.0 * std::f64::consts::PI * cutoff as f64 / self.sample_rate as f64;
+        let d = 1.0 / q as f64;
+        let b = 0.5 * ((1.0 - (d / 2.0) * o.sin()) / (1.0 + (d / 2.0) * o.sin()));
+        let y = (0.5 + b) * o.cos();
+        let a0 = (0.5 + b - y) / 2.0;
+        self.set_coefficients(a0, 2.0 * a0, a0, -2.0 * y, 2.0 * b);
+    }
+
+    pub fn second_order_hpf_coefficients(&mut self, cutoff: f32, q: f32) {
+        let o = 2.0 * std::f64::consts::PI * cutoff as f64 / self.sample_rate as f64;
+        let d = 1.0 / q as f64;
+        let b = 0.5 * ((1.0 - (d / 2.0) * o.sin()) / (1.0 + (d / 2.0) * o.sin()));
+        let y = (0.5 + b) * o.cos();
+        let a0 = (0.5 + b + y) / 2.0;
+        self.set_coefficients(a0, -2.0 * a0, a0, -2.0 * y, 2.0 * b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the output high-pass at its hardest setting, 20 Hz at 192 kHz
+    const SAMPLE_RATE: f32 = 192000.0;
+    const CUTOFF: f32 = 20.0;
+    const Q: f32 = 0.707;
+
+    fn df1() -> BiquadFilter {
+        let mut filter = BiquadFilter::new();
+        filter.set_sample_rate(SAMPLE_RATE);
+        filter.coefficients(FilterType::HighPass2, CUTOFF, Q, 1.0);
+        filter
+    }
+
+    fn tdf<T: FilterPrecision>() -> TdfBiquadFilter<T> {
+        let mut filter = TdfBiquadFilter::<T>::new();
+        filter.set_sample_rate(SAMPLE_RATE);
+        filter.second_order_hpf_coefficients(CUTOFF, Q);
+        filter
+    }
+
+    // direct form I in f64 throughout, the reference for the others
+    struct Reference {
+        coeffs: [f64; 5],
+        state: [f64; 4],
+    }
+
+    impl Reference {
+        fn new() -> Self {
+            let o = 2.0 * std::f64::consts::PI * CUTOFF as f64 / SAMPLE_RATE as f64;
+            let d = 1.0 / Q as f64;
+            let b = 0.5 * ((1.0 - (d / 2.0) * o.sin()) / (1.0 + (d / 2.0) * o.sin()));
+            let y = (0.5 + b) * o.cos();
+            let a0 = (0.5 + b + y) / 2.0;
+            Self {
+                coeffs: [a0, -2.0 * a0, a0, -2.0 * y, 2.0 * b],
+                state: [0.0; 4],
+            }
+        }
+
+        fn process(&mut self, x: f64) -> f64 {
+            let [a0, a1, a2, b0, b1] = self.coeffs;
+            let [x1, x2, y1, y2] = self.state;
+            let y = a0 * x + a1 * x1 + a2 * x2 - b0 * y1 - b1 * y2;
+            self.state = [x, x1, y, y1];
+            y
+        }
+    }
+
+    // peak level of a sine at f Hz, after the filter has settled
+    fn sine_gain(mut process: impl FnMut(f32) -> f32, f: f32) -> f32 {
+        let n = SAMPLE_RATE as usize;
+        let mut peak: f32 = 0.0;
+        for i in 0..2 * n {
+            let x = (2.0 * PI * f * i as f32 / SAMPLE_RATE).sin();
+            let y = process(x);
+            if i >= n {
+                peak = peak.max(y.abs());
+            }
+        }
+        peak
+    }
+
+    #[test]
+    fn dc_is_rejected() {
+        let (mut df1, mut tdf32, mut tdf64) = (df1(), tdf::<f32>(), tdf::<f64>());
+        let (mut y_df1, mut y32, mut y64) = (0.0, 0.0, 0.0);
+        for _ in 0..5 * SAMPLE_RATE as usize {
+            y_df1 = df1.process_left(0.5);
+            y32 = tdf32.process_left(0.5);
+            y64 = tdf64.process_left(0.5);
+        }
+        assert!(y64.abs() < 1e-9, "f64 transposed direct form leaves {:e}", y64);
+        assert!(y_df1.abs() < 1e-9, "direct form I leaves {:e}", y_df1);
+        // f32 state can't hold the tiny difference between the two state values
+        assert!(y32.abs() > 1000.0 * y64.abs());
+    }
+
+    #[test]
+    fn cutoff_gain_is_accurate() {
+        let target = std::f32::consts::FRAC_1_SQRT_2;
+        let mut tdf64 = tdf::<f64>();
+        let gain64 = sine_gain(|x| tdf64.process_left(x), CUTOFF);
+        let mut df1 = df1();
+        let gain_df1 = sine_gain(|x| df1.process_left(x), CUTOFF);
+        assert!((gain64 - target).abs() < 0.001, "f64 gain at the cutoff is {}", gain64);
+        // f32 coefficients move the poles
+        assert!((gain64 - target).abs() < (gain_df1 - target).abs());
+    }
+
+    // error against the f64 reference for a 1 kHz sine, relative to the signal level
+    #[test]
+    fn noise_floor() {
+        let mut reference = Reference::new();
+        let (mut df1, mut tdf32, mut tdf64) = (df1(), tdf::<f32>(), tdf::<f64>());
+        let (mut signal, mut error_df1, mut error32, mut error64) = (0.0, 0.0, 0.0, 0.0);
+        let n = SAMPLE_RATE as usize;
+        for i in 0..3 * n {
+            let x = 0.5 * (2.0 * PI * 1000.0 * i as f32 / SAMPLE_RATE).sin();
+            let r = reference.process(x as f64);
+            let y_df1 = df1.process_left(x) as f64;
+            let y32 = tdf32.process_left(x) as f64;
+            let y64 = tdf64.process_left(x) as f64;
+            if i >= n {
+                signal += r * r;
+                error_df1 += (y_df1 - r) * (y_df1 - r);
+                error32 += (y32 - r) * (y32 - r);
+                error64 += (y64 - r) * (y64 - r);
+            }
+        }
+        let db = |error: f64| 10.0 * (error / signal).log10();
+        // only the rounding of the f32 output is left
+        assert!(db(error64) < -140.0, "f64 transposed direct form error is {:.1} dB", db(error64));
+        assert!(db(error64) < db(error32) - 60.0);
+        assert!(db(error64) < db(error_df1) - 60.0);
+    }
+
+    // the saturation only drives the state that is fed back, so the current sample leaves the
+    // filter at its full level even above 1
+    #[test]
+    fn feedback_saturation_leaves_the_output_unclipped() {
+        for saturation_type in [SaturationType::Tanh, SaturationType::SoftClip, SaturationType::Diode] {
+            let mut filter = BiquadFilter::new();
+            filter.set_sample_rate(SAMPLE_RATE);
+            filter.coefficients(FilterType::Peak, 1000.0, 0.707, 10.0_f32.powf(12.0 / 20.0));
+            let linear = filter.process_left(2.0);
+            filter.reset_filter();
+            filter.set_feedback_saturation(saturation_type);
+            let driven = filter.process_left(2.0);
+            assert!(linear > 1.5 && driven == linear, "an impulse of 2 leaves as {} instead of {}", driven, linear);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::filter::{BiquadFilter, FilterDesign, FilterType};
+use crate::formant::FormantFilter;
+use crate::ladder::LadderFilter;
+use crate::saturation::SaturationType;
+use crate::svf::StateVariableFilter;
+
+// one stereo filter, dispatches to the structure that implements the selected filter type
+pub struct FilterSection {
+    filter_type: FilterType,
+    biquad: BiquadFilter,
+    svf: StateVariableFilter,
+    ladder: LadderFilter,
+    formant: FormantFilter,
+}
+
+impl FilterSection {
+    pub fn new() -> Self {
+        Self {
+            filter_type: FilterType::LowPass1,
+            biquad: BiquadFilter::new(),
+            svf: StateVariableFilter::new(),
+            ladder: LadderFilter::new(),
+            formant: FormantFilter::new(),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.biquad.set_sample_rate(sample_rate);
+        self.svf.set_sample_rate(sample_rate);
+        self.ladder.set_sample_rate(sample_rate);
+        self.formant.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_feedback_saturation(&mut self, saturation_type: SaturationType) {
+        self.biquad.set_feedback_saturation(saturation_type);
+        self.svf.set_feedback_saturation(saturation_type);
+    }
+
+    pub fn set_design(&mut self, design: FilterDesign) {
+        self.biquad.set_design(design);
+    }
+
+    pub fn reset_filter(&mut self) {
+        self.biquad.reset_filter();
+        self.svf.reset_filter();
+        self.ladder.reset_filter();
+        self.formant.reset_filter();
+    }
+
+    pub fn set_params(&mut self, filter_type: FilterType, cutoff: f32, q: f32, gain: f32, morph: f32, vowel: f32) {
+        self.filter_type = filter_type;
+        match filter_type {
+            FilterType::Morph => self.svf.coefficients(cutoff, q, morph),
+            t if t.is_ladder() => self.ladder.coefficients(t, cutoff, q),
+            FilterType::Formant => self.formant.coefficients(vowel),
+            _ => self.biquad.coefficients(filter_type, cutoff, q, gain),
+        }
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        match self.filter_type {
+            FilterType::Morph => self.svf.process_left(x),
+            t if t.is_ladder() => self.ladder.process_left(x),
+            FilterType::Formant => self.formant.process_left(x),
+            _ => self.biquad.process_left(x),
+        }
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        match self.filter_type {
+            FilterType::Morph => self.svf.process_right(x),
+            t if t.is_ladder() => self.ladder.process_right(x),
+            FilterType::Formant => self.formant.process_right(x),
+            _ => self.biquad.process_right(x),
+        }
+    }
+}
@@ -0,0 +1,377 @@
+// Linear phase mode. The magnitude response of filter A is sampled on an FFT grid,
+// given a constant delay of half the kernel length and turned into a windowed FIR
+// (frequency sampling). The FIR runs as a uniformly partitioned overlap-save
+// convolution, so the cost per sample stays low even for long kernels.
+//
+// Kernels are designed on a background thread. The audio thread only copies finished
+// kernels into buffers it allocated in initialize(), so it never allocates or frees.
+//
+// The FIR takes the place of filter A inside the channel mode and routing. Every path
+// that bypasses it (the unfiltered channel, filter B in parallel and split routing) gets
+// the input delayed by the same latency, so the paths stay aligned.
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+
+use crate::filter::{BiquadFilter, FilterDesign, FilterType};
+use crate::filter_section::FilterSection;
+
+// input is collected in blocks of this size before it is convolved
+pub const PARTITION_SIZE: usize = 256;
+
+// level of the impulse used to measure the non-biquad filter types, low enough
+// to keep the tanh in the ladder filter linear
+const IMPULSE_LEVEL: f32 = 0.001;
+
+// about 80ms of taps, so the kernel still resolves the lowest cutoffs at high sample rates
+pub fn fir_length(sample_rate: f32) -> usize {
+    ((sample_rate * 0.08) as usize).next_power_of_two().max(2 * PARTITION_SIZE)
+}
+
+// the kernel is centered at half its length, the partitioning adds one more block
+pub fn latency_samples(fir_length: usize) -> u32 {
+    (fir_length / 2 + PARTITION_SIZE) as u32
+}
+
+// everything the kernel depends on, sent to the background thread
+#[derive(Clone, Copy, PartialEq)]
+pub struct KernelSpec {
+    pub filter_type: FilterType,
+    pub design: FilterDesign,
+    pub cutoff: f32,
+    pub q: f32,
+    pub gain: f32,
+    pub morph: f32,
+    pub vowel: f32,
+    pub sample_rate: f32,
+}
+
+// a kernel for one channel, in dual channel modes the right/side channel has its own
+#[derive(Clone, Copy, PartialEq)]
+pub struct KernelRequest {
+    pub channel: usize,
+    pub spec: KernelSpec,
+}
+
+// spectra of the kernel partitions, one after another
+pub struct FirKernel {
+    fir_length: usize,
+    // a kernel finished after a sample rate change is for the old rate, even when the length
+    // is the same
+    sample_rate: f32,
+    spectra: Vec<Complex<f32>>,
+}
+
+// hands finished kernels from the background thread to the audio thread, one slot per
+// channel. the old kernel is replaced (and freed) on the background thread
+pub struct KernelExchange {
+    pub kernels: [Option<FirKernel>; 2],
+    pub fresh: [bool; 2],
+}
+
+impl KernelExchange {
+    pub fn new() -> Self {
+        Self {
+            kernels: [None, None],
+            fresh: [false; 2],
+        }
+    }
+}
+
+pub fn design_kernel(spec: KernelSpec) -> FirKernel {
+    let n = fir_length(spec.sample_rate);
+    let magnitudes = magnitude_response(spec, n);
+
+    let mut planner = RealFftPlanner::<f32>::new();
+    let inverse = planner.plan_fft_inverse(n);
+
+    // zero phase magnitude delayed by n / 2, e^(-j * pi * k) flips every other bin
+    let mut spectrum: Vec<Complex<f32>> = magnitudes
+        .iter()
+        .enumerate()
+        .map(|(k, m)| {
+            let m = if k % 2 == 0 { *m } else { -*m };
+            Complex::new(m as f32, 0.0)
+        })
+        .collect();
+    let mut impulse = inverse.make_output_vec();
+    inverse.process(&mut spectrum, &mut impulse).unwrap();
+
+    // blackman window centered on the peak at n / 2, the fft is unnormalized
+    for (i, h) in impulse.iter_mut().enumerate() {
+        let phase = 2.0 * PI * i as f64 / n as f64;
+        let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+        *h *= window as f32 / n as f32;
+    }
+
+    // the convolution fft is unnormalized too, fold its scaling into the kernel
+    let forward = planner.plan_fft_forward(2 * PARTITION_SIZE);
+    let scale = 1.0 / (2 * PARTITION_SIZE) as f32;
+    let mut spectra = Vec::with_capacity(n / PARTITION_SIZE * (PARTITION_SIZE + 1));
+    let mut time = forward.make_input_vec();
+    let mut partition_spectrum = forward.make_output_vec();
+    for partition in impulse.chunks(PARTITION_SIZE) {
+        time.fill(0.0);
+        for (t, h) in time.iter_mut().zip(partition.iter()) {
+            *t = *h * scale;
+        }
+        forward.process(&mut time, &mut partition_spectrum).unwrap();
+        spectra.extend_from_slice(&partition_spectrum);
+    }
+
+    FirKernel {
+        fir_length: n,
+        sample_rate: spec.sample_rate,
+        spectra,
+    }
+}
+
+// magnitude at the n / 2 + 1 bins of an n point fft
+fn magnitude_response(spec: KernelSpec, n: usize) -> Vec<f64> {
+    match spec.filter_type {
+        FilterType::Morph | FilterType::Formant => measured_magnitude(spec, n),
+        t if t.is_ladder() => measured_magnitude(spec, n),
+        _ => analytic_magnitude(spec, n),
+    }
+}
+
+// evaluates the biquad transfer function on the unit circle
+fn analytic_magnitude(spec: KernelSpec, n: usize) -> Vec<f64> {
+    let mut biquad = BiquadFilter::new();
+    biquad.set_sample_rate(spec.sample_rate);
+    biquad.set_design(spec.design);
+    biquad.coefficients(spec.filter_type, spec.cutoff, spec.q, spec.gain);
+    let c: Vec<f64> = (0..7).map(|i| biquad.get_coefficient(i) as f64).collect();
+
+    (0..=n / 2)
+        .map(|k| {
+            let w = 2.0 * PI * k as f64 / n as f64;
+            let z1 = Complex::new(0.0, -w).exp();
+            let z2 = z1 * z1;
+            let numerator = z1 * c[1] + z2 * c[2] + c[0];
+            let denominator = z1 * c[3] + z2 * c[4] + 1.0;
+            (numerator / denominator * c[5] + c[6]).norm()
+        })
+        .collect()
+}
+
+// the svf, ladder and formant filters are not single biquads, their magnitude is
+// taken from the spectrum of their impulse response
+fn measured_magnitude(spec: KernelSpec, n: usize) -> Vec<f64> {
+    let mut section = FilterSection::new();
+    section.set_sample_rate(spec.sample_rate);
+    section.set_design(spec.design);
+    section.set_params(spec.filter_type, spec.cutoff, spec.q, spec.gain, spec.morph, spec.vowel);
+
+    let mut impulse: Vec<f32> = (0..n)
+        .map(|i| {
+            let x = if i == 0 { IMPULSE_LEVEL } else { 0.0 };
+            section.process_left(x) / IMPULSE_LEVEL
+        })
+        .collect();
+
+    let forward = RealFftPlanner::<f32>::new().plan_fft_forward(n);
+    let mut spectrum = forward.make_output_vec();
+    forward.process(&mut impulse, &mut spectrum).unwrap();
+    spectrum.iter().map(|x| x.norm() as f64).collect()
+}
+
+struct FirChannel {
+    // previous and current input block
+    input: Vec<f32>,
+    // spectra of the past input blocks, newest at fdl_position
+    fdl: Vec<Complex<f32>>,
+    output: Vec<f32>,
+    // input delayed by the latency, for the paths that bypass the FIR
+    dry: Vec<f32>,
+}
+
+impl FirChannel {
+    fn new(partitions: usize, latency: usize) -> Self {
+        Self {
+            input: vec![0.0; 2 * PARTITION_SIZE],
+            fdl: vec![Complex::new(0.0, 0.0); partitions * (PARTITION_SIZE + 1)],
+            output: vec![0.0; PARTITION_SIZE],
+            dry: vec![0.0; latency],
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.fdl.fill(Complex::new(0.0, 0.0));
+        self.output.fill(0.0);
+        self.dry.fill(0.0);
+    }
+}
+
+// filtered output of both channels, and the input delayed by the same latency
+#[derive(Clone, Copy)]
+pub struct FirFrame {
+    pub filtered: (f32, f32),
+    pub dry: (f32, f32),
+}
+
+// stereo uniformly partitioned convolution. both channels use the left kernel unless
+// dual is set, then the right channel uses the right kernel
+pub struct FirConvolver {
+    fir_length: usize,
+    sample_rate: f32,
+    partitions: usize,
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+
+    kernels: [Vec<Complex<f32>>; 2],
+    // kernels before the last update, the first block after an update fades from them
+    previous_kernels: [Vec<Complex<f32>>; 2],
+    crossfade: [bool; 2],
+    dual: bool,
+
+    channels: [FirChannel; 2],
+    position: usize,
+    fdl_position: usize,
+    dry_position: usize,
+
+    time: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    accumulator: Vec<Complex<f32>>,
+    new_output: Vec<f32>,
+    forward_scratch: Vec<Complex<f32>>,
+    inverse_scratch: Vec<Complex<f32>>,
+}
+
+impl FirConvolver {
+    // allocates, call from initialize(). both kernels need the same length
+    pub fn new(left_kernel: &FirKernel, right_kernel: &FirKernel) -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(2 * PARTITION_SIZE);
+        let inverse = planner.plan_fft_inverse(2 * PARTITION_SIZE);
+        let partitions = left_kernel.fir_length / PARTITION_SIZE;
+        let latency = latency_samples(left_kernel.fir_length) as usize;
+
+        Self {
+            fir_length: left_kernel.fir_length,
+            sample_rate: left_kernel.sample_rate,
+            partitions,
+            time: forward.make_input_vec(),
+            spectrum: forward.make_output_vec(),
+            accumulator: forward.make_output_vec(),
+            new_output: vec![0.0; PARTITION_SIZE],
+            forward_scratch: forward.make_scratch_vec(),
+            inverse_scratch: inverse.make_scratch_vec(),
+            forward,
+            inverse,
+            kernels: [left_kernel.spectra.clone(), right_kernel.spectra.clone()],
+            previous_kernels: [left_kernel.spectra.clone(), right_kernel.spectra.clone()],
+            crossfade: [false; 2],
+            dual: false,
+            channels: [FirChannel::new(partitions, latency), FirChannel::new(partitions, latency)],
+            position: 0,
+            fdl_position: 0,
+            dry_position: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.reset();
+        }
+        self.position = 0;
+        self.fdl_position = 0;
+        self.dry_position = 0;
+        self.crossfade = [false; 2];
+    }
+
+    pub fn set_dual(&mut self, dual: bool) {
+        self.dual = dual;
+    }
+
+    // copies a new kernel for the channel in, kernels designed for another sample rate
+    // are ignored
+    pub fn load_kernel(&mut self, channel: usize, kernel: &FirKernel) {
+        if kernel.fir_length != self.fir_length || kernel.sample_rate != self.sample_rate {
+            return;
+        }
+        std::mem::swap(&mut self.kernels[channel], &mut self.previous_kernels[channel]);
+        self.kernels[channel].copy_from_slice(&kernel.spectra);
+        self.crossfade[channel] = true;
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> FirFrame {
+        let i = self.position;
+        self.channels[0].input[PARTITION_SIZE + i] = left;
+        self.channels[1].input[PARTITION_SIZE + i] = right;
+        let filtered = (self.channels[0].output[i], self.channels[1].output[i]);
+
+        let d = self.dry_position;
+        let dry = (self.channels[0].dry[d], self.channels[1].dry[d]);
+        self.channels[0].dry[d] = left;
+        self.channels[1].dry[d] = right;
+        self.dry_position = (d + 1) % self.channels[0].dry.len();
+
+        self.position += 1;
+        if self.position == PARTITION_SIZE {
+            self.position = 0;
+            self.process_block();
+        }
+        FirFrame { filtered, dry }
+    }
+
+    fn process_block(&mut self) {
+        let bins = PARTITION_SIZE + 1;
+        self.fdl_position = (self.fdl_position + 1) % self.partitions;
+
+        for c in 0..2 {
+            let k = if self.dual { c } else { 0 };
+            self.time.copy_from_slice(&self.channels[c].input);
+            self.forward
+                .process_with_scratch(&mut self.time, &mut self.spectrum, &mut self.forward_scratch)
+                .unwrap();
+            let offset = self.fdl_position * bins;
+            self.channels[c].fdl[offset..offset + bins].copy_from_slice(&self.spectrum);
+
+            self.convolve(c, k, false);
+            if self.crossfade[k] {
+                self.new_output.copy_from_slice(&self.channels[c].output);
+                self.convolve(c, k, true);
+                let channel = &mut self.channels[c];
+                for (i, (y, faded)) in channel.output.iter_mut().zip(self.new_output.iter()).enumerate() {
+                    let t = i as f32 / PARTITION_SIZE as f32;
+                    *y = *y * (1.0 - t) + faded * t;
+                }
+            }
+
+            let channel = &mut self.channels[c];
+            channel.input.copy_within(PARTITION_SIZE.., 0);
+        }
+        self.crossfade = [false; 2];
+    }
+
+    // sums the delayed input spectra of channel c times the partitions of kernel k, the
+    // last half of the inverse transform is the output block (overlap-save)
+    fn convolve(&mut self, c: usize, k: usize, previous: bool) {
+        let bins = PARTITION_SIZE + 1;
+        let kernel = if previous { &self.previous_kernels[k] } else { &self.kernels[k] };
+        let channel = &mut self.channels[c];
+
+        self.accumulator.fill(Complex::new(0.0, 0.0));
+        for p in 0..self.partitions {
+            let block = (self.fdl_position + self.partitions - p) % self.partitions;
+            let x = &channel.fdl[block * bins..(block + 1) * bins];
+            let h = &kernel[p * bins..(p + 1) * bins];
+            for ((acc, x), h) in self.accumulator.iter_mut().zip(x.iter()).zip(h.iter()) {
+                *acc += x * h;
+            }
+        }
+
+        // the inverse transform needs real dc and nyquist bins
+        self.accumulator[0].im = 0.0;
+        self.accumulator[PARTITION_SIZE].im = 0.0;
+        self.inverse
+            .process_with_scratch(&mut self.accumulator, &mut self.time, &mut self.inverse_scratch)
+            .unwrap();
+        channel.output.copy_from_slice(&self.time[PARTITION_SIZE..]);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::filter::BiquadFilter;
+
+const FORMANTS: usize = 5;
+const VOWELS: usize = 5;
+
+// parallel band-passes are quiet compared to the input, roughly +12dB brings them back
+const MAKEUP_GAIN: f32 = 4.0;
+
+// formant frequencies (Hz), levels (dB) and bandwidths (Hz) of a bass voice
+// singing A, E, I, O, U
+const FREQUENCIES: [[f32; FORMANTS]; VOWELS] = [
+    [600.0, 1040.0, 2250.0, 2450.0, 2750.0],
+    [400.0, 1620.0, 2400.0, 2800.0, 3100.0],
+    [250.0, 1750.0, 2600.0, 3050.0, 3340.0],
+    [400.0, 750.0, 2400.0, 2600.0, 2900.0],
+    [350.0, 600.0, 2400.0, 2675.0, 2950.0],
+];
+
+const LEVELS_DB: [[f32; FORMANTS]; VOWELS] = [
+    [0.0, -7.0, -9.0, -9.0, -20.0],
+    [0.0, -12.0, -9.0, -12.0, -18.0],
+    [0.0, -30.0, -16.0, -22.0, -28.0],
+    [0.0, -11.0, -21.0, -20.0, -40.0],
+    [0.0, -20.0, -32.0, -28.0, -36.0],
+];
+
+const BANDWIDTHS: [[f32; FORMANTS]; VOWELS] = [
+    [60.0, 70.0, 110.0, 120.0, 130.0],
+    [40.0, 80.0, 100.0, 120.0, 120.0],
+    [60.0, 90.0, 100.0, 120.0, 120.0],
+    [40.0, 80.0, 100.0, 120.0, 120.0],
+    [40.0, 80.0, 100.0, 120.0, 120.0],
+];
+
+// vowel filter made of parallel band-pass biquads, one per formant
+#[derive(Clone, Copy)]
+pub struct FormantFilter {
+    bands: [BiquadFilter; FORMANTS],
+    gains: [f32; FORMANTS],
+    sample_rate: f32,
+}
+
+impl FormantFilter {
+    pub fn new() -> Self {
+        Self {
+            bands: [BiquadFilter::new(); FORMANTS],
+            gains: [0.0; FORMANTS],
+            sample_rate: 44100.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for band in self.bands.iter_mut() {
+            band.set_sample_rate(sample_rate);
+        }
+    }
+
+    pub fn reset_filter(&mut self) {
+        for band in self.bands.iter_mut() {
+            band.reset_filter();
+        }
+    }
+
+    // vowel goes from 0 to 4 (A, E, I, O, U), values in between blend the neighbouring vowels
+    pub fn coefficients(&mut self, vowel: f32) {
+        let vowel = vowel.clamp(0.0, (VOWELS - 1) as f32);
+        let i = (vowel.floor() as usize).min(VOWELS - 2);
+        let t = vowel - i as f32;
+
+        for f in 0..FORMANTS {
+            let frequency = lerp(FREQUENCIES[i][f], FREQUENCIES[i + 1][f], t)
+                .min(self.sample_rate * 0.45);
+            let bandwidth = lerp(BANDWIDTHS[i][f], BANDWIDTHS[i + 1][f], t);
+            let level = lerp(LEVELS_DB[i][f], LEVELS_DB[i + 1][f], t);
+
+            self.bands[f].band_pass_coefficients(self.sample_rate, frequency, frequency / bandwidth);
+            self.gains[f] = MAKEUP_GAIN * 10.0_f32.powf(level / 20.0);
+        }
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let mut y = 0.0;
+        for (band, gain) in self.bands.iter_mut().zip(self.gains.iter()) {
+            y += gain * band.process_left(x);
+        }
+        y
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let mut y = 0.0;
+        for (band, gain) in self.bands.iter_mut().zip(self.gains.iter()) {
+            y += gain * band.process_right(x);
+        }
+        y
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
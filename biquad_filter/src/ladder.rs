@@ -0,0 +1,100 @@
+use std::f32::consts::PI;
+
+use crate::filter::FilterType;
+
+// feedback amount reached at the top of the resonance parameter. the ladder
+// self-oscillates above 4.0, which is around a resonance of 25
+const MAX_RESONANCE: f32 = 4.4;
+
+// 4 pole moog-style ladder filter. the stages are TPT one-pole low-passes and the
+// feedback loop is solved without a unit delay (zero-delay feedback). the feedback
+// sum goes through tanh, so at high resonance the filter self-oscillates at a stable level
+#[derive(Clone, Copy)]
+pub struct LadderFilter {
+    // one-pole gain g / (1 + g)
+    big_g: f32,
+    one_plus_g: f32,
+    k: f32,
+    filter_type: FilterType,
+
+    l_s: [f32; 4],
+    r_s: [f32; 4],
+
+    sample_rate: f32,
+}
+
+impl LadderFilter {
+    pub fn new() -> Self {
+        Self {
+            big_g: 0.0,
+            one_plus_g: 1.0,
+            k: 0.0,
+            filter_type: FilterType::LadderLowPass24,
+            l_s: [0.0; 4],
+            r_s: [0.0; 4],
+            sample_rate: 44100.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn reset_filter(&mut self) {
+        self.l_s = [0.0; 4];
+        self.r_s = [0.0; 4];
+    }
+
+    // q is the resonance parameter (0.5 to 30), mapped onto the ladder feedback amount
+    pub fn coefficients(&mut self, filter_type: FilterType, cutoff: f32, q: f32) {
+        let cutoff = cutoff.min(self.sample_rate * 0.49);
+        let g = (PI * cutoff / self.sample_rate).tan();
+        self.one_plus_g = 1.0 + g;
+        self.big_g = g / self.one_plus_g;
+
+        let resonance = ((q - 0.5) / 29.5).clamp(0.0, 1.0);
+        self.k = MAX_RESONANCE * resonance.sqrt();
+        self.filter_type = filter_type;
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let mut s = self.l_s;
+        let y = self.tick(x, &mut s);
+        self.l_s = s;
+        y
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let mut s = self.r_s;
+        let y = self.tick(x, &mut s);
+        self.r_s = s;
+        y
+    }
+
+    fn tick(&self, x: f32, s: &mut [f32; 4]) -> f32 {
+        let g = self.big_g;
+
+        // output of the last stage is g^4 * u + sigma, solve for the ladder input u
+        let sigma = (g * g * g * s[0] + g * g * s[1] + g * s[2] + s[3]) / self.one_plus_g;
+        let u = (x - self.k * sigma) / (1.0 + self.k * g * g * g * g);
+        let u = u.tanh();
+
+        let mut stage_in = u;
+        let mut y = [0.0; 4];
+        for i in 0..4 {
+            let v = (stage_in - s[i]) * g;
+            y[i] = v + s[i];
+            s[i] = y[i] + v;
+            stage_in = y[i];
+        }
+
+        match self.filter_type {
+            FilterType::LadderLowPass12 => y[1],
+            FilterType::LadderBandPass12 => 2.0 * y[0] - 2.0 * y[1],
+            FilterType::LadderBandPass24 => 4.0 * y[1] - 8.0 * y[2] + 4.0 * y[3],
+            FilterType::LadderHighPass12 => u - 2.0 * y[0] + y[1],
+            FilterType::LadderHighPass24 => u - 4.0 * y[0] + 6.0 * y[1] - 4.0 * y[2] + y[3],
+            _ => y[3],
+        }
+    }
+}
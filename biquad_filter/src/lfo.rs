@@ -1,10 +1,10 @@
 use std::{f32::consts::PI, ops::Range};
 
-use rand::Rng;
-
 pub struct LFO {
     pub rate: f32,
     phase: f32,
+    // phase the LFO goes back to on reset
+    initial_phase: f32,
     pub sample_rate: f32
 }
 
@@ -14,15 +14,7 @@ impl LFO {
             sample_rate,
             rate,
             phase: 0.0,
-        }
-    }
-
-    pub fn new_random_phase(sample_rate: f32, rate: f32) -> Self {
-        let mut rng = rand::thread_rng();
-        Self {
-            sample_rate,
-            rate,
-            phase: rng.gen_range(0.0..(2.0 * PI))
+            initial_phase: 0.0,
         }
     }
 
@@ -30,7 +22,8 @@ impl LFO {
         Self {
             sample_rate,
             rate,
-            phase
+            phase,
+            initial_phase: phase,
         }
     }
 
@@ -46,10 +39,14 @@ impl LFO {
         scaled
     }
 
+    pub fn reset(&mut self) {
+        self.phase = self.initial_phase;
+    }
+
     pub fn update_lfo(&mut self) {
         self.phase += 2.0 * std::f32::consts::PI * self.rate / self.sample_rate;
         if self.phase > 2.0 * PI {
             self.phase -= 2.0 * PI;
         }
     }
-}
\ No newline at end of file
+}
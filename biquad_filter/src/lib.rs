@@ -1,7 +1,13 @@
+use channel_mode::ChannelMode;
 use chorus::Chorus;
-use filter::FilterType;
+use filter::{FilterDesign, FilterType};
+use filter_section::FilterSection;
+use fir::{FirConvolver, KernelExchange, KernelRequest, KernelSpec};
+use oversampler::{Oversampler, OVERSAMPLING};
+use routing::Routing;
+use saturation::SaturationType;
 use nih_plug::prelude::*;
-use std::{sync::{Arc, mpsc::channel}, collections::VecDeque, env};
+use std::{sync::{Arc, Mutex, mpsc::channel}, collections::VecDeque, env};
 
 use nih_plug::prelude::*;
 use nih_plug_vizia::ViziaState;
@@ -11,23 +17,70 @@ mod lfo;
 mod editor;
 mod chorus;
 mod filter;
+mod channel_mode;
+mod saturation;
+mod oversampler;
+mod svf;
+mod filter_section;
+mod matched;
+mod ladder;
+mod formant;
+mod routing;
+mod fir;
 
 const MAX_BLOCK_SIZE: usize = 64;
+// minimum number of samples between two linear phase kernel requests
+const KERNEL_UPDATE_INTERVAL: usize = 1024;
 
 struct FilterPlugin {
     params: Arc<FilterPluginParams>,
     sample_rate: f32,
-    filter: filter::BiquadFilter,
+    filter: FilterSection,
     prev_filter_type : filter::FilterType,
+    // used only in dual modes, filters the right/side channel
+    filter_rs: FilterSection,
+    prev_channel_mode: ChannelMode,
+    // second filter section, connected to the first one according to the routing
+    filter_b: FilterSection,
+    prev_filter_type_b: filter::FilterType,
+    // routing and balance gains of the current sample, set in process()
+    routing: Routing,
+    balance_gains: (f32, f32),
+    vowel_lfo: lfo::LFO,
+    // drive stage runs oversampled, together with the filters
+    oversampler: Oversampler,
+    prev_saturation: SaturationType,
+    prev_drive_feedback: bool,
+    oversampled_filters: bool,
     scratch_buffer: ScratchBuffer,
-
-    output_hpf: filter::BiquadFilter,
+    // linear phase mode, the kernels are designed on the background thread
+    fir: Option<FirConvolver>,
+    kernel_exchange: Arc<Mutex<KernelExchange>>,
+    requested_kernels: [Option<KernelSpec>; 2],
+    samples_since_request: usize,
+    // linear phase state of the current block
+    linear_phase: bool,
+    // reported to the host again when it changes
+    latency: u32,
+
+    output_hpf: filter::TdfBiquadFilter<f64>,
 }
 
 struct ScratchBuffer {
     cutoff: [f32; MAX_BLOCK_SIZE],
     resonance: [f32; MAX_BLOCK_SIZE],
     gain: [f32; MAX_BLOCK_SIZE],
+    cutoff_rs: [f32; MAX_BLOCK_SIZE],
+    gain_rs: [f32; MAX_BLOCK_SIZE],
+    drive: [f32; MAX_BLOCK_SIZE],
+    morph: [f32; MAX_BLOCK_SIZE],
+    vowel: [f32; MAX_BLOCK_SIZE],
+    vowel_lfo_rate: [f32; MAX_BLOCK_SIZE],
+    vowel_lfo_depth: [f32; MAX_BLOCK_SIZE],
+    cutoff_b: [f32; MAX_BLOCK_SIZE],
+    resonance_b: [f32; MAX_BLOCK_SIZE],
+    gain_b: [f32; MAX_BLOCK_SIZE],
+    balance: [f32; MAX_BLOCK_SIZE],
 }
 
 impl Default for ScratchBuffer {
@@ -36,6 +89,17 @@ impl Default for ScratchBuffer {
             cutoff: [0.0; MAX_BLOCK_SIZE],
             resonance: [0.0; MAX_BLOCK_SIZE],
             gain: [0.0; MAX_BLOCK_SIZE],
+            cutoff_rs: [0.0; MAX_BLOCK_SIZE],
+            gain_rs: [0.0; MAX_BLOCK_SIZE],
+            drive: [0.0; MAX_BLOCK_SIZE],
+            morph: [0.0; MAX_BLOCK_SIZE],
+            vowel: [0.0; MAX_BLOCK_SIZE],
+            vowel_lfo_rate: [0.0; MAX_BLOCK_SIZE],
+            vowel_lfo_depth: [0.0; MAX_BLOCK_SIZE],
+            cutoff_b: [0.0; MAX_BLOCK_SIZE],
+            resonance_b: [0.0; MAX_BLOCK_SIZE],
+            gain_b: [0.0; MAX_BLOCK_SIZE],
+            balance: [0.0; MAX_BLOCK_SIZE],
         }
     }
 }
@@ -48,6 +112,9 @@ struct FilterPluginParams {
     #[id = "FilterType"]
     filter_type: EnumParam<filter::FilterType>,
 
+    #[id = "Design"]
+    design: EnumParam<FilterDesign>,
+
     #[id = "Cutoff"]
     cutoff: FloatParam,
 
@@ -56,6 +123,66 @@ struct FilterPluginParams {
 
     #[id = "Gain"]
     gain: FloatParam,
+
+    // only used by the morph filter type
+    #[id = "Morph"]
+    morph: FloatParam,
+
+    // only used by the formant filter type
+    #[id = "Vowel"]
+    vowel: FloatParam,
+
+    #[id = "VowelLfoRate"]
+    vowel_lfo_rate: FloatParam,
+
+    #[id = "VowelLfoDepth"]
+    vowel_lfo_depth: FloatParam,
+
+    #[id = "ChannelMode"]
+    channel_mode: EnumParam<ChannelMode>,
+
+    // in dual modes cutoff and gain above drive the left/mid channel, these drive the right/side
+    #[id = "CutoffRS"]
+    cutoff_rs: FloatParam,
+
+    #[id = "GainRS"]
+    gain_rs: FloatParam,
+
+    #[id = "Saturation"]
+    saturation: EnumParam<SaturationType>,
+
+    #[id = "Drive"]
+    drive: FloatParam,
+
+    // also saturate inside the resonant feedback of the filter
+    #[id = "DriveFeedback"]
+    drive_feedback: BoolParam,
+
+    #[id = "AutoGain"]
+    auto_gain: BoolParam,
+
+    #[id = "Routing"]
+    routing: EnumParam<Routing>,
+
+    // level of filter A against filter B in parallel and split routing
+    #[id = "Balance"]
+    balance: FloatParam,
+
+    #[id = "FilterTypeB"]
+    filter_type_b: EnumParam<filter::FilterType>,
+
+    #[id = "CutoffB"]
+    cutoff_b: FloatParam,
+
+    #[id = "ResonanceB"]
+    resonance_b: FloatParam,
+
+    #[id = "GainB"]
+    gain_b: FloatParam,
+
+    // runs filter A as a linear phase FIR, adds latency
+    #[id = "LinearPhase"]
+    linear_phase: BoolParam,
 }
 
 impl Default for FilterPlugin {
@@ -63,10 +190,27 @@ impl Default for FilterPlugin {
         Self {
             params: Arc::new(FilterPluginParams::default()),
             sample_rate: 44100.0,
-            filter: filter::BiquadFilter::new(),
+            filter: FilterSection::new(),
             prev_filter_type: filter::FilterType::LowPass1,
+            filter_rs: FilterSection::new(),
+            prev_channel_mode: ChannelMode::Stereo,
+            filter_b: FilterSection::new(),
+            prev_filter_type_b: filter::FilterType::LowPass1,
+            routing: Routing::Single,
+            balance_gains: (1.0, 1.0),
+            vowel_lfo: lfo::LFO::new(44100.0, 0.5),
+            oversampler: Oversampler::new(),
+            prev_saturation: SaturationType::Off,
+            prev_drive_feedback: false,
+            oversampled_filters: false,
             scratch_buffer: ScratchBuffer::default(),
-            output_hpf: filter::BiquadFilter::new(),
+            fir: None,
+            kernel_exchange: Arc::new(Mutex::new(KernelExchange::new())),
+            requested_kernels: [None, None],
+            samples_since_request: 0,
+            linear_phase: false,
+            latency: 0,
+            output_hpf: filter::TdfBiquadFilter::new(),
         }
     }
 }
@@ -78,6 +222,8 @@ impl Default for FilterPluginParams {
 
             filter_type: EnumParam::new("Filter Type", filter::FilterType::LowPass1),
 
+            design: EnumParam::new("Design", FilterDesign::Bilinear),
+
             // cutoff parameter in Hz, from 20 to 20k
             cutoff: FloatParam::new("Cutoff", 5000.0, FloatRange::Skewed { min: 20.0, max: 20000.0, factor: 0.5 } )
             .with_unit("")
@@ -105,6 +251,337 @@ impl Default for FilterPluginParams {
             .with_unit(" dB")
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            // morph parameter from 0 to 3, low-pass -> band-pass -> high-pass -> notch
+            morph: FloatParam::new("Morph", 0.0, FloatRange::Linear { min: 0.0, max: 3.0 })
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_value_to_string(v2s_morph()),
+
+            // vowel parameter from 0 to 4, A -> E -> I -> O -> U
+            vowel: FloatParam::new("Vowel", 0.0, FloatRange::Linear { min: 0.0, max: 4.0 })
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_value_to_string(v2s_vowel()),
+
+            vowel_lfo_rate: FloatParam::new("Vowel LFO Rate", 0.5, FloatRange::Skewed { min: 0.02, max: 10.0, factor: 0.3 })
+            .with_smoother(SmoothingStyle::Linear(15.0))
+            .with_unit("Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            // how many vowels the LFO sweeps in each direction
+            vowel_lfo_depth: FloatParam::new("Vowel LFO Depth", 0.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+            .with_smoother(SmoothingStyle::Linear(15.0))
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            channel_mode: EnumParam::new("Channel Mode", ChannelMode::Stereo),
+
+            cutoff_rs: FloatParam::new("R/S Cutoff", 5000.0, FloatRange::Skewed { min: 20.0, max: 20000.0, factor: 0.5 } )
+            .with_unit("")
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            gain_rs: FloatParam::new(
+                "R/S Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-30.0),
+                    max: util::db_to_gain(30.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            saturation: EnumParam::new("Saturation", SaturationType::Off),
+
+            // drive parameter from 0dB to 36dB
+            drive: FloatParam::new(
+                "Drive",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(0.0),
+                    max: util::db_to_gain(36.0),
+                    factor: FloatRange::gain_skew_factor(0.0, 36.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            drive_feedback: BoolParam::new("Resonance Drive", false),
+
+            auto_gain: BoolParam::new("Auto Gain", true),
+
+            routing: EnumParam::new("Routing", Routing::Single),
+
+            balance: FloatParam::new("Balance", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            filter_type_b: EnumParam::new("Filter B Type", filter::FilterType::HighPass1),
+
+            cutoff_b: FloatParam::new("Filter B Cutoff", 200.0, FloatRange::Skewed { min: 20.0, max: 20000.0, factor: 0.5 } )
+            .with_unit("")
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            resonance_b: FloatParam::new("Filter B Resonance", 0.707, FloatRange::Linear { min: 0.5, max: 30.0 })
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_unit("")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            gain_b: FloatParam::new(
+                "Filter B Gain",
+                util::db_to_gain(0.0),
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-30.0),
+                    max: util::db_to_gain(30.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(50.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            linear_phase: BoolParam::new("Linear Phase", false),
+        }
+    }
+}
+
+pub fn v2s_morph() -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        let names = ["LP", "BP", "HP", "Notch"];
+        let i = (value.floor() as usize).min(2);
+        let t = value - i as f32;
+        if t < 0.005 {
+            names[i].to_string()
+        } else if t > 0.995 {
+            names[i + 1].to_string()
+        } else {
+            format!("{} > {} {:.0}%", names[i], names[i + 1], t * 100.0)
+        }
+    })
+}
+
+pub fn v2s_vowel() -> Arc<dyn Fn(f32) -> String + Send + Sync> {
+    Arc::new(move |value| {
+        let names = ["A", "E", "I", "O", "U"];
+        let i = (value.floor() as usize).min(3);
+        let t = value - i as f32;
+        if t < 0.005 {
+            names[i].to_string()
+        } else if t > 0.995 {
+            names[i + 1].to_string()
+        } else {
+            format!("{} > {} {:.0}%", names[i], names[i + 1], t * 100.0)
+        }
+    })
+}
+
+impl FilterPlugin {
+    // the filters run at the oversampled rate whenever the drive stage is active. in linear
+    // phase mode only the drive is oversampled, filter B runs next to the FIR at the base rate
+    fn update_saturation(&mut self, saturation: SaturationType, drive_feedback: bool) {
+        let oversampled = saturation != SaturationType::Off && !self.linear_phase;
+        if oversampled != self.oversampled_filters {
+            let filter_sample_rate = if oversampled {
+                self.sample_rate * OVERSAMPLING as f32
+            } else {
+                self.sample_rate
+            };
+            self.filter.set_sample_rate(filter_sample_rate);
+            self.filter_rs.set_sample_rate(filter_sample_rate);
+            self.filter_b.set_sample_rate(filter_sample_rate);
+            self.filter.reset_filter();
+            self.filter_rs.reset_filter();
+            self.filter_b.reset_filter();
+            self.oversampler.reset();
+            self.oversampled_filters = oversampled;
+        }
+
+        let feedback_saturation = if drive_feedback { saturation } else { SaturationType::Off };
+        self.filter.set_feedback_saturation(feedback_saturation);
+        self.filter_rs.set_feedback_saturation(feedback_saturation);
+        self.filter_b.set_feedback_saturation(feedback_saturation);
+
+        self.prev_saturation = saturation;
+        self.prev_drive_feedback = drive_feedback;
+    }
+
+    // in linear phase mode the FIR delays everything by half its length, and the oversampled
+    // drive stage in front of it adds the group delay of its filters. the minimum phase
+    // filters don't report their phase shift, so neither does the drive stage there
+    fn latency_samples(&self, saturation: SaturationType) -> u32 {
+        if !self.linear_phase {
+            return 0;
+        }
+        let drive = if saturation != SaturationType::Off { oversampler::LATENCY_SAMPLES } else { 0 };
+        fir::latency_samples(fir::fir_length(self.sample_rate)) + drive
+    }
+
+    // drive -> filter, oversampled, in linear phase mode only the drive is. auto gain
+    // level-matches the output to the input
+    fn process_frame_driven(
+        &mut self,
+        channel_mode: ChannelMode,
+        saturation: SaturationType,
+        drive: f32,
+        auto_gain: bool,
+        left: f32,
+        right: f32,
+    ) -> (f32, f32) {
+        let makeup = if auto_gain { saturation::makeup_gain(saturation, drive) } else { 1.0 };
+
+        let mut frames = self.oversampler.upsample(left, right);
+        if self.linear_phase {
+            for frame in frames.iter_mut() {
+                *frame = (
+                    saturation::saturate(saturation, frame.0 * drive),
+                    saturation::saturate(saturation, frame.1 * drive),
+                );
+            }
+            let (l, r) = self.oversampler.downsample(frames);
+            let (l, r) = self.process_frame(channel_mode, l, r);
+            return (l * makeup, r * makeup);
+        }
+
+        for frame in frames.iter_mut() {
+            let l = saturation::saturate(saturation, frame.0 * drive);
+            let r = saturation::saturate(saturation, frame.1 * drive);
+            let (l, r) = self.process_frame(channel_mode, l, r);
+            *frame = (l * makeup, r * makeup);
+        }
+        self.oversampler.downsample(frames)
+    }
+
+    // filters A and B connected according to the routing, for the left/mid channel.
+    // in linear phase mode fir holds the FIR output, which replaces filter A, and the
+    // input delayed by the same latency, which filter B gets in parallel and split routing
+    fn chain_left(&mut self, x: f32, fir: Option<(f32, f32)>) -> f32 {
+        let (gain_a, gain_b) = self.balance_gains;
+        let dry = fir.map_or(x, |(_, dry)| dry);
+        let filter = &mut self.filter;
+        let mut filter_a = |x| fir.map_or_else(|| filter.process_left(x), |(filtered, _)| filtered);
+        match self.routing {
+            Routing::Single => filter_a(x),
+            Routing::Serial => self.filter_b.process_left(filter_a(x)),
+            Routing::Parallel => {
+                gain_a * filter_a(x) + gain_b * self.filter_b.process_left(dry)
+            }
+            Routing::Split => gain_a * filter_a(x),
+        }
+    }
+
+    // same for the right/side channel, in dual modes filter A uses the r/s cutoff and gain
+    fn chain_right(&mut self, x: f32, dual: bool, fir: Option<(f32, f32)>) -> f32 {
+        let (gain_a, gain_b) = self.balance_gains;
+        let dry = fir.map_or(x, |(_, dry)| dry);
+        let filter = if dual { &mut self.filter_rs } else { &mut self.filter };
+        let mut filter_a = |x| fir.map_or_else(|| filter.process_right(x), |(filtered, _)| filtered);
+        match self.routing {
+            Routing::Single => filter_a(x),
+            Routing::Serial => self.filter_b.process_right(filter_a(x)),
+            Routing::Parallel => {
+                gain_a * filter_a(x) + gain_b * self.filter_b.process_right(dry)
+            }
+            Routing::Split => gain_b * self.filter_b.process_right(dry),
+        }
+    }
+
+    // routes one stereo frame through the filter(s) according to the channel mode.
+    // left channel state of the filter is used for left/mid, right channel state for right/side
+    fn process_frame(&mut self, channel_mode: ChannelMode, left: f32, right: f32) -> (f32, f32) {
+        let (first, second) = if channel_mode.is_mid_side() {
+            channel_mode::mid_side_encode(left, right)
+        } else {
+            (left, right)
+        };
+
+        // the unfiltered channel is delayed along with the FIR
+        let (fir_first, fir_second) = match self.fir.as_mut() {
+            Some(fir) if self.linear_phase => {
+                let frame = fir.process(first, second);
+                (Some((frame.filtered.0, frame.dry.0)), Some((frame.filtered.1, frame.dry.1)))
+            }
+            _ => (None, None),
+        };
+        let dry_first = fir_first.map_or(first, |(_, dry)| dry);
+        let dry_second = fir_second.map_or(second, |(_, dry)| dry);
+
+        let dual = channel_mode.is_dual();
+        let (first, second) = match channel_mode {
+            ChannelMode::Left | ChannelMode::Mid => (self.chain_left(first, fir_first), dry_second),
+            ChannelMode::Right | ChannelMode::Side => (dry_first, self.chain_right(second, dual, fir_second)),
+            _ => (self.chain_left(first, fir_first), self.chain_right(second, dual, fir_second)),
+        };
+
+        if channel_mode.is_mid_side() {
+            channel_mode::mid_side_decode(first, second)
+        } else {
+            (first, second)
+        }
+    }
+
+    // linear phase kernel for the smoothed parameters of filter A at the start of the block,
+    // channel 1 is the right/side channel of the dual modes
+    fn kernel_spec(&self, channel: usize, filter_type: FilterType, design: FilterDesign) -> KernelSpec {
+        let (cutoff, gain) = if channel == 0 {
+            (self.scratch_buffer.cutoff[0], self.scratch_buffer.gain[0])
+        } else {
+            (self.scratch_buffer.cutoff_rs[0], self.scratch_buffer.gain_rs[0])
+        };
+        KernelSpec {
+            filter_type,
+            design,
+            cutoff,
+            q: self.scratch_buffer.resonance[0],
+            gain,
+            morph: self.scratch_buffer.morph[0],
+            vowel: self.scratch_buffer.vowel[0],
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    // asks the background thread for new kernels when the parameters changed, at most
+    // once per KERNEL_UPDATE_INTERVAL, and picks up the kernels once they are ready.
+    // the right/side kernel is only kept up to date in dual modes
+    fn update_kernel(
+        &mut self,
+        filter_type: FilterType,
+        design: FilterDesign,
+        dual: bool,
+        block_len: usize,
+        context: &mut impl ProcessContext<Self>,
+    ) {
+        self.samples_since_request += block_len;
+        if self.samples_since_request >= KERNEL_UPDATE_INTERVAL {
+            let channels = if dual { 2 } else { 1 };
+            for channel in 0..channels {
+                let spec = self.kernel_spec(channel, filter_type, design);
+                if self.requested_kernels[channel] != Some(spec) {
+                    context.execute_background(KernelRequest { channel, spec });
+                    self.requested_kernels[channel] = Some(spec);
+                    self.samples_since_request = 0;
+                }
+            }
+        }
+
+        if let (Ok(mut exchange), Some(fir)) = (self.kernel_exchange.try_lock(), self.fir.as_mut()) {
+            for channel in 0..2 {
+                if exchange.fresh[channel] {
+                    if let Some(kernel) = exchange.kernels[channel].as_ref() {
+                        fir.load_kernel(channel, kernel);
+                    }
+                    exchange.fresh[channel] = false;
+                }
+            }
         }
     }
 }
@@ -136,14 +613,27 @@ impl Plugin for FilterPlugin {
     // from plain byte buffers.
     type SysExMessage = ();
     // More advanced plugins can use this to run expensive background tasks. See the field's
-    // documentation for more information. `()` means that the plugin does not have any background
-    // tasks.
-    type BackgroundTask = ();
+    // documentation for more information. Linear phase kernels are designed in the background.
+    type BackgroundTask = KernelRequest;
 
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
 
+    fn task_executor(&mut self) -> TaskExecutor<Self> {
+        let kernel_exchange = self.kernel_exchange.clone();
+        Box::new(move |request: KernelRequest| {
+            let kernel = fir::design_kernel(request.spec);
+            let old_kernel = {
+                let mut exchange = kernel_exchange.lock().unwrap();
+                exchange.fresh[request.channel] = true;
+                exchange.kernels[request.channel].replace(kernel)
+            };
+            // freed here, outside the lock and off the audio thread
+            drop(old_kernel);
+        })
+    }
+
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
@@ -153,8 +643,40 @@ impl Plugin for FilterPlugin {
         self.sample_rate = _buffer_config.sample_rate as f32;
 
         self.filter.set_sample_rate(self.sample_rate);
+        self.filter_rs.set_sample_rate(self.sample_rate);
+        self.filter_b.set_sample_rate(self.sample_rate);
+        self.vowel_lfo.sample_rate = self.sample_rate;
+        self.oversampler.set_sample_rate(self.sample_rate);
+        // filters get the oversampled rate again on the next block if needed
+        self.prev_saturation = SaturationType::Off;
+        self.oversampled_filters = false;
         self.output_hpf.set_sample_rate(self.sample_rate);
-        self.output_hpf.coefficients(FilterType::HighPass2, 25.0, 0.707, 1.0);
+        self.output_hpf.second_order_hpf_coefficients(25.0, 0.707);
+
+        // the first kernel is designed here, later ones on the background thread
+        let spec = KernelSpec {
+            filter_type: self.params.filter_type.value(),
+            design: self.params.design.value(),
+            cutoff: self.params.cutoff.value(),
+            q: self.params.resonance.value(),
+            gain: self.params.gain.value(),
+            morph: self.params.morph.value(),
+            vowel: self.params.vowel.value(),
+            sample_rate: self.sample_rate,
+        };
+        let spec_rs = KernelSpec {
+            cutoff: self.params.cutoff_rs.value(),
+            gain: self.params.gain_rs.value(),
+            ..spec
+        };
+        let mut fir = FirConvolver::new(&fir::design_kernel(spec), &fir::design_kernel(spec_rs));
+        fir.set_dual(self.params.channel_mode.value().is_dual());
+        self.fir = Some(fir);
+        self.requested_kernels = [Some(spec), Some(spec_rs)];
+        self.samples_since_request = 0;
+        self.linear_phase = self.params.linear_phase.value();
+        self.latency = self.latency_samples(self.params.saturation.value());
+        _context.set_latency_samples(self.latency);
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
@@ -164,6 +686,15 @@ impl Plugin for FilterPlugin {
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.filter.reset_filter();
+        self.filter_rs.reset_filter();
+        self.filter_b.reset_filter();
+        self.oversampler.reset();
+        self.output_hpf.reset_filter();
+        self.vowel_lfo.reset();
+        if let Some(fir) = self.fir.as_mut() {
+            fir.reset();
+        }
     }
 
     fn process(
@@ -173,14 +704,32 @@ impl Plugin for FilterPlugin {
         _context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
 
-        for (_, block) in buffer.iter_blocks(MAX_BLOCK_SIZE) {
+        for (_, mut block) in buffer.iter_blocks(MAX_BLOCK_SIZE) {
             let block_len = block.samples();
             
             let filter_type = self.params.filter_type.value();
+            let channel_mode = self.params.channel_mode.value();
+            let design = self.params.design.value();
+            let saturation = self.params.saturation.value();
+            let drive_feedback = self.params.drive_feedback.value();
+            let auto_gain = self.params.auto_gain.value();
+            let filter_type_b = self.params.filter_type_b.value();
+            let routing = self.params.routing.value();
 
             let cutoff = &mut self.scratch_buffer.cutoff;
             let resonance = &mut self.scratch_buffer.resonance;
             let gain = &mut self.scratch_buffer.gain;
+            let cutoff_rs = &mut self.scratch_buffer.cutoff_rs;
+            let gain_rs = &mut self.scratch_buffer.gain_rs;
+            let drive = &mut self.scratch_buffer.drive;
+            let morph = &mut self.scratch_buffer.morph;
+            let vowel = &mut self.scratch_buffer.vowel;
+            let vowel_lfo_rate = &mut self.scratch_buffer.vowel_lfo_rate;
+            let vowel_lfo_depth = &mut self.scratch_buffer.vowel_lfo_depth;
+            let cutoff_b = &mut self.scratch_buffer.cutoff_b;
+            let resonance_b = &mut self.scratch_buffer.resonance_b;
+            let gain_b = &mut self.scratch_buffer.gain_b;
+            let balance = &mut self.scratch_buffer.balance;
 
             self.params
             .cutoff.smoothed.next_block(cutoff, block_len);
@@ -191,29 +740,140 @@ impl Plugin for FilterPlugin {
             self.params
             .gain.smoothed.next_block(gain, block_len);
 
-            if filter_type != self.prev_filter_type {
+            self.params
+            .cutoff_rs.smoothed.next_block(cutoff_rs, block_len);
+
+            self.params
+            .gain_rs.smoothed.next_block(gain_rs, block_len);
+
+            self.params
+            .drive.smoothed.next_block(drive, block_len);
+
+            self.params
+            .morph.smoothed.next_block(morph, block_len);
+
+            self.params
+            .vowel.smoothed.next_block(vowel, block_len);
+
+            self.params
+            .vowel_lfo_rate.smoothed.next_block(vowel_lfo_rate, block_len);
+
+            self.params
+            .vowel_lfo_depth.smoothed.next_block(vowel_lfo_depth, block_len);
+
+            self.params
+            .cutoff_b.smoothed.next_block(cutoff_b, block_len);
+
+            self.params
+            .resonance_b.smoothed.next_block(resonance_b, block_len);
+
+            self.params
+            .gain_b.smoothed.next_block(gain_b, block_len);
+
+            self.params
+            .balance.smoothed.next_block(balance, block_len);
+
+            let linear_phase = self.params.linear_phase.value();
+            let linear_phase_changed = linear_phase != self.linear_phase;
+            if linear_phase_changed {
+                self.linear_phase = linear_phase;
+                if let Some(fir) = self.fir.as_mut().filter(|_| linear_phase) {
+                    fir.reset();
+                }
+            }
+
+            if saturation != self.prev_saturation || drive_feedback != self.prev_drive_feedback || linear_phase_changed {
+                self.update_saturation(saturation, drive_feedback);
+            }
+
+            let latency = self.latency_samples(saturation);
+            if latency != self.latency {
+                self.latency = latency;
+                _context.set_latency_samples(latency);
+            }
+
+            if filter_type != self.prev_filter_type || channel_mode != self.prev_channel_mode {
                 self.prev_filter_type = filter_type;
+                self.prev_channel_mode = channel_mode;
                 self.filter.reset_filter();
+                self.filter_rs.reset_filter();
+                if let Some(fir) = self.fir.as_mut() {
+                    fir.set_dual(channel_mode.is_dual());
+                }
+            }
+
+            if filter_type_b != self.prev_filter_type_b || routing != self.routing {
+                self.prev_filter_type_b = filter_type_b;
+                self.routing = routing;
+                self.filter_b.reset_filter();
             }
 
-            for (channel, block_channel) in block.into_iter().enumerate() {
-                for (num, sample) in block_channel.into_iter().enumerate() {
-                    let cutoff1 = cutoff[num];
-                    let mut resonance1 = resonance[num];
-                    let gain1 = gain[num];
+            self.filter.set_design(design);
+            self.filter_rs.set_design(design);
+            self.filter_b.set_design(design);
 
-                    if filter_type == FilterType::SecondOrderAllPass {
-                        resonance1 = resonance1.clamp(1.0, 1000.0);
+            if linear_phase {
+                self.update_kernel(filter_type, design, channel_mode.is_dual(), block_len, _context);
+            }
+
+            for (num, mut channel_samples) in block.iter_samples().enumerate() {
+                let cutoff1 = self.scratch_buffer.cutoff[num];
+                let mut resonance1 = self.scratch_buffer.resonance[num];
+                let gain1 = self.scratch_buffer.gain[num];
+                let morph1 = self.scratch_buffer.morph[num];
+
+                self.vowel_lfo.rate = self.scratch_buffer.vowel_lfo_rate[num];
+                let vowel1 = (self.scratch_buffer.vowel[num]
+                    + self.vowel_lfo.next_value() * self.scratch_buffer.vowel_lfo_depth[num])
+                    .clamp(0.0, 4.0);
+                self.vowel_lfo.update_lfo();
+
+                if filter_type == FilterType::SecondOrderAllPass {
+                    resonance1 = resonance1.clamp(1.0, 1000.0);
+                }
+
+                self.balance_gains = routing::balance_gains(self.scratch_buffer.balance[num]);
+
+                self.filter.set_params(filter_type, cutoff1, resonance1, gain1, morph1, vowel1);
+                if channel_mode.is_dual() {
+                    self.filter_rs.set_params(
+                        filter_type,
+                        self.scratch_buffer.cutoff_rs[num],
+                        resonance1,
+                        self.scratch_buffer.gain_rs[num],
+                        morph1,
+                        vowel1,
+                    );
+                }
+                if self.routing != Routing::Single {
+                    let mut resonance_b1 = self.scratch_buffer.resonance_b[num];
+                    if filter_type_b == FilterType::SecondOrderAllPass {
+                        resonance_b1 = resonance_b1.clamp(1.0, 1000.0);
                     }
+                    // morph and vowel are shared with filter A
+                    self.filter_b.set_params(
+                        filter_type_b,
+                        self.scratch_buffer.cutoff_b[num],
+                        resonance_b1,
+                        self.scratch_buffer.gain_b[num],
+                        morph1,
+                        vowel1,
+                    );
+                }
+
+                unsafe {
+                    let l = *channel_samples.get_unchecked_mut(0);
+                    let r = *channel_samples.get_unchecked_mut(1);
 
-                    self.filter.coefficients(filter_type, cutoff1, resonance1, gain1);
-                    if channel == 0 {
-                        *sample = self.filter.process_left(*sample);
-                        *sample = self.output_hpf.process_left(*sample);
+                    let (l_out, r_out) = if saturation == SaturationType::Off {
+                        self.process_frame(channel_mode, l, r)
                     } else {
-                        *sample = self.filter.process_right(*sample);
-                        *sample = self.output_hpf.process_right(*sample);
-                    }
+                        let drive1 = self.scratch_buffer.drive[num];
+                        self.process_frame_driven(channel_mode, saturation, drive1, auto_gain, l, r)
+                    };
+
+                    *channel_samples.get_unchecked_mut(0) = self.output_hpf.process_left(l_out);
+                    *channel_samples.get_unchecked_mut(1) = self.output_hpf.process_right(r_out);
                 }
             }
         }
@@ -0,0 +1,284 @@
+// Matched magnitude biquad designs, based on Martin Vicanek's "Matched Second Order
+// Digital Filters". Poles are placed by impulse invariance and the numerator is solved
+// so the digital magnitude equals the analog prototype at DC, at the cutoff and at
+// nyquist. Unlike the bilinear transform, this does not squash the response near nyquist.
+//
+// Everything is calculated in f64, low cutoffs at high sample rates lose too much
+// precision in f32.
+
+use std::f64::consts::PI;
+
+use crate::filter::{BiquadCoefficients, FilterType};
+
+// returns None for filter types that have no matched design. all-passes keep a flat
+// magnitude with the bilinear transform anyway, and a matched notch loses its null
+pub fn coefficients(filter_type: FilterType, sample_rate: f32, cutoff: f32, q: f32, gain: f32) -> Option<BiquadCoefficients> {
+    let sample_rate = sample_rate as f64;
+    let cutoff = (cutoff as f64).min(0.49 * sample_rate);
+    let q = q as f64;
+    // gain is in dB, the same as in the bilinear designs
+    let u = 10.0_f64.powf(gain as f64 / 20.0);
+
+    match filter_type {
+        FilterType::LowPass1 => Some(first_order(sample_rate, cutoff, 1.0, |w| {
+            1.0 / (1.0 + w * w)
+        })),
+        FilterType::HighPass1 => Some(first_order(sample_rate, cutoff, 1.0, |w| {
+            w * w / (1.0 + w * w)
+        })),
+        FilterType::LowShelf => {
+            let wp = 4.0 / (1.0 + u);
+            Some(first_order(sample_rate, cutoff, wp, |w| {
+                (w * w + u * u * wp * wp) / (w * w + wp * wp)
+            }))
+        },
+        FilterType::HighShelf => {
+            let wp = (1.0 + u) / 4.0;
+            Some(first_order(sample_rate, cutoff, wp, |w| {
+                (u * u * w * w + wp * wp) / (w * w + wp * wp)
+            }))
+        },
+        FilterType::LowPass2 => Some(second_order(sample_rate, cutoff, q, |w| {
+            1.0 / resonant_denominator(w, 1.0 / q)
+        })),
+        FilterType::HighPass2 => Some(high_pass(sample_rate, cutoff, q)),
+        FilterType::BandPass => Some(second_order(sample_rate, cutoff, q, |w| {
+            (w / q) * (w / q) / resonant_denominator(w, 1.0 / q)
+        })),
+        FilterType::Peak => {
+            // same prototype as the bilinear peak filter, boosts widen the zeros
+            // and cuts narrow the poles
+            let (zero_damping, pole_damping) = if gain >= 0.0 {
+                (u / q, 1.0 / q)
+            } else {
+                (1.0 / q, 1.0 / (q * u))
+            };
+            Some(second_order(sample_rate, cutoff, 1.0 / pole_damping, |w| {
+                resonant_denominator(w, zero_damping) / resonant_denominator(w, pole_damping)
+            }))
+        },
+        _ => None,
+    }
+}
+
+// squared magnitude of s^2 + damping * s + 1 at s = jw
+fn resonant_denominator(w: f64, damping: f64) -> f64 {
+    (1.0 - w * w) * (1.0 - w * w) + (w * damping) * (w * damping)
+}
+
+fn impulse_invariant_poles(w0: f64, pole_q: f64) -> (f64, f64) {
+    let zeta = 0.5 / pole_q;
+    let a1 = if zeta <= 1.0 {
+        -2.0 * (-zeta * w0).exp() * ((1.0 - zeta * zeta).sqrt() * w0).cos()
+    } else {
+        -2.0 * (-zeta * w0).exp() * ((zeta * zeta - 1.0).sqrt() * w0).cosh()
+    };
+    let a2 = (-2.0 * zeta * w0).exp();
+    (a1, a2)
+}
+
+// magnitude_squared takes a frequency normalized to the cutoff
+fn second_order(sample_rate: f64, cutoff: f64, pole_q: f64, magnitude_squared: impl Fn(f64) -> f64) -> BiquadCoefficients {
+    let w0 = 2.0 * PI * cutoff / sample_rate;
+    let (a1, a2) = impulse_invariant_poles(w0, pole_q);
+
+    // |A(w)|^2 = A0 * phi0 + A1 * phi1 + A2 * phi2, the same holds for the numerator
+    let big_a0 = (1.0 + a1 + a2) * (1.0 + a1 + a2);
+    let big_a1 = (1.0 - a1 + a2) * (1.0 - a1 + a2);
+    let big_a2 = -4.0 * a2;
+
+    let phi1 = (w0 / 2.0).sin() * (w0 / 2.0).sin();
+    let phi0 = 1.0 - phi1;
+    let phi2 = 4.0 * phi0 * phi1;
+
+    let nyquist = sample_rate / (2.0 * cutoff);
+    let big_b0 = magnitude_squared(0.0) * big_a0;
+    let big_b1 = magnitude_squared(nyquist) * big_a1;
+    let big_b2 = (magnitude_squared(1.0) * (big_a0 * phi0 + big_a1 * phi1 + big_a2 * phi2)
+        - big_b0 * phi0
+        - big_b1 * phi1)
+        / phi2;
+
+    let sqrt_b0 = big_b0.sqrt();
+    let sqrt_b1 = big_b1.sqrt();
+    let w = 0.5 * (sqrt_b0 + sqrt_b1);
+    let b0 = 0.5 * (w + (w * w + big_b2).max(0.0).sqrt());
+    let b1 = 0.5 * (sqrt_b0 - sqrt_b1);
+    let b2 = if b0 != 0.0 { -big_b2 / (4.0 * b0) } else { 0.0 };
+
+    BiquadCoefficients::new(b0 as f32, b1 as f32, b2 as f32, a1 as f32, a2 as f32, 1.0, 0.0)
+}
+
+// the high-pass needs a double zero at DC to keep its 12 dB/oct slope, which leaves only
+// the nyquist level to match. the cutoff follows from the poles
+fn high_pass(sample_rate: f64, cutoff: f64, q: f64) -> BiquadCoefficients {
+    let w0 = 2.0 * PI * cutoff / sample_rate;
+    let (a1, a2) = impulse_invariant_poles(w0, q);
+
+    let nyquist = sample_rate / (2.0 * cutoff);
+    let magnitude_squared = nyquist.powi(4) / resonant_denominator(nyquist, 1.0 / q);
+    let b0 = (magnitude_squared * (1.0 - a1 + a2) * (1.0 - a1 + a2)).sqrt() / 4.0;
+
+    BiquadCoefficients::new(b0 as f32, -2.0 * b0 as f32, b0 as f32, a1 as f32, a2 as f32, 1.0, 0.0)
+}
+
+// the pole is solved so the magnitude also matches at the pole frequency wp (normalized
+// to the cutoff). falls back to an impulse invariant pole if the magnitude is flat there
+fn first_order(sample_rate: f64, cutoff: f64, wp: f64, magnitude_squared: impl Fn(f64) -> f64) -> BiquadCoefficients {
+    let nyquist = sample_rate / (2.0 * cutoff);
+    let m0 = magnitude_squared(0.0);
+    let mp = magnitude_squared(wp);
+    let mn = magnitude_squared(nyquist);
+
+    let wp_digital = (2.0 * PI * cutoff * wp / sample_rate).min(0.99 * PI);
+    let phi1 = (wp_digital / 2.0).sin() * (wp_digital / 2.0).sin();
+    let phi0 = 1.0 - phi1;
+
+    // ((1 - p) / (1 + p))^2
+    let r = -(mn - mp) * phi1 / ((m0 - mp) * phi0);
+    let p = if r.is_finite() && r > 0.0 {
+        (1.0 - r.sqrt()) / (1.0 + r.sqrt())
+    } else {
+        (-2.0 * PI * cutoff * wp / sample_rate).exp()
+    };
+
+    let sqrt_b0 = (m0 * (1.0 - p) * (1.0 - p)).sqrt();
+    let sqrt_b1 = (mn * (1.0 + p) * (1.0 + p)).sqrt();
+    let b0 = 0.5 * (sqrt_b0 + sqrt_b1);
+    let b1 = 0.5 * (sqrt_b0 - sqrt_b1);
+
+    BiquadCoefficients::new(b0 as f32, b1 as f32, 0.0, -p as f32, 0.0, 1.0, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterDesign;
+    use crate::filter_section::FilterSection;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    // below this the f32 coefficients decide the level rather than the design
+    const STOP_BAND_FLOOR: f64 = -60.0;
+
+    fn db(x: f64) -> f64 {
+        20.0 * x.log10()
+    }
+
+    // analog prototype magnitude at f Hz
+    fn analog_magnitude(filter_type: FilterType, cutoff: f64, q: f64, gain: f64, f: f64) -> f64 {
+        let w = f / cutoff;
+        let u = 10.0_f64.powf(gain / 20.0);
+        let magnitude_squared = match filter_type {
+            FilterType::LowPass2 => 1.0 / resonant_denominator(w, 1.0 / q),
+            FilterType::HighPass2 => w.powi(4) / resonant_denominator(w, 1.0 / q),
+            FilterType::BandPass => (w / q).powi(2) / resonant_denominator(w, 1.0 / q),
+            FilterType::Peak if gain >= 0.0 => resonant_denominator(w, u / q) / resonant_denominator(w, 1.0 / q),
+            FilterType::Peak => resonant_denominator(w, 1.0 / q) / resonant_denominator(w, 1.0 / (q * u)),
+            FilterType::LowShelf => {
+                let wp = 4.0 / (1.0 + u);
+                (w * w + u * u * wp * wp) / (w * w + wp * wp)
+            },
+            FilterType::HighShelf => {
+                let wp = (1.0 + u) / 4.0;
+                (u * u * w * w + wp * wp) / (w * w + wp * wp)
+            },
+            _ => unreachable!(),
+        };
+        magnitude_squared.sqrt()
+    }
+
+    // largest deviation in dB from the analog prototype, from 20 Hz up to max_frequency
+    fn max_error(filter_type: FilterType, cutoff: f32, q: f32, gain: f32, max_frequency: f64) -> f64 {
+        let coeffs = coefficients(filter_type, SAMPLE_RATE, cutoff, q, gain).unwrap();
+        let mut max_error: f64 = 0.0;
+        for i in 0..=200 {
+            let f = 20.0 * (max_frequency / 20.0).powf(i as f64 / 200.0);
+            let w = 2.0 * PI * f / SAMPLE_RATE as f64;
+            let digital = db(coeffs.magnitude(w));
+            let analog = db(analog_magnitude(filter_type, cutoff as f64, q as f64, gain as f64, f));
+            if analog < STOP_BAND_FLOOR {
+                continue;
+            }
+            max_error = max_error.max((digital - analog).abs());
+        }
+        max_error
+    }
+
+    // low cutoffs are near exact up to a quarter of the sample rate, and every cutoff stays
+    // within a couple of dB right up to nyquist, where the bilinear transform is off by tens of dB
+    fn assert_matches(filter_type: FilterType, q: f32, gain: f32, tolerance: f64) {
+        let nyquist = SAMPLE_RATE as f64 / 2.0;
+        for cutoff in [100.0, 1000.0] {
+            let error = max_error(filter_type, cutoff, q, gain, nyquist / 4.0);
+            assert!(error < 0.25, "cutoff {} Hz is off by {:.3} dB below nyquist / 4", cutoff, error);
+        }
+        for cutoff in [100.0, 1000.0, 5000.0, 12000.0] {
+            let error = max_error(filter_type, cutoff, q, gain, 0.95 * nyquist);
+            assert!(error < tolerance, "cutoff {} Hz is off by {:.3} dB", cutoff, error);
+        }
+    }
+
+    #[test]
+    fn low_pass_matches_analog() {
+        assert_matches(FilterType::LowPass2, 0.707, 0.0, 1.5);
+        assert_matches(FilterType::LowPass2, 4.0, 0.0, 1.5);
+    }
+
+    #[test]
+    fn high_pass_matches_analog() {
+        assert_matches(FilterType::HighPass2, 0.707, 0.0, 0.5);
+        assert_matches(FilterType::HighPass2, 4.0, 0.0, 2.0);
+    }
+
+    #[test]
+    fn band_pass_matches_analog() {
+        assert_matches(FilterType::BandPass, 0.707, 0.0, 1.5);
+        assert_matches(FilterType::BandPass, 4.0, 0.0, 1.5);
+    }
+
+    #[test]
+    fn peak_matches_analog() {
+        assert_matches(FilterType::Peak, 1.0, 12.0, 1.0);
+        assert_matches(FilterType::Peak, 1.0, -12.0, 2.5);
+    }
+
+    #[test]
+    fn shelves_match_analog() {
+        assert_matches(FilterType::LowShelf, 0.707, 12.0, 1.0);
+        assert_matches(FilterType::LowShelf, 0.707, -12.0, 1.5);
+        assert_matches(FilterType::HighShelf, 0.707, 12.0, 1.0);
+        assert_matches(FilterType::HighShelf, 0.707, -12.0, 1.0);
+    }
+
+    // the bilinear transform forces a zero at nyquist, the matched low-pass keeps the
+    // analog level there
+    #[test]
+    fn low_pass_keeps_nyquist_level() {
+        let coeffs = coefficients(FilterType::LowPass2, SAMPLE_RATE, 12000.0, 0.707, 0.0).unwrap();
+        let nyquist = SAMPLE_RATE as f64 / 2.0;
+        let analog = analog_magnitude(FilterType::LowPass2, 12000.0, 0.707, 0.0, nyquist);
+        assert!((db(coeffs.magnitude(PI)) - db(analog)).abs() < 0.01);
+    }
+
+    // the plugin's gain parameters are linear, a cut has to reach the designs as a negative
+    // dB gain through FilterSection like it does in process()
+    #[test]
+    fn cut_through_the_gain_parameter() {
+        let cut = 10.0_f32.powf(-12.0 / 20.0);
+        for design in [FilterDesign::Bilinear, FilterDesign::Matched] {
+            let mut section = FilterSection::new();
+            section.set_sample_rate(SAMPLE_RATE);
+            section.set_design(design);
+            section.set_params(FilterType::Peak, 1000.0, 1.0, cut, 0.0, 0.0);
+            let mut peak: f32 = 0.0;
+            for i in 0..SAMPLE_RATE as usize {
+                let x = (2.0 * PI * 1000.0 * i as f64 / SAMPLE_RATE as f64).sin() as f32;
+                let y = section.process_left(x);
+                if i >= SAMPLE_RATE as usize / 2 {
+                    peak = peak.max(y.abs());
+                }
+            }
+            assert!((db(peak as f64) + 12.0).abs() < 0.1, "a -12 dB cut is {:.2} dB", db(peak as f64));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::filter::BiquadFilter;
+
+pub const OVERSAMPLING: usize = 2;
+
+// Q values of the second order sections of a 6th order butterworth filter
+const BUTTERWORTH_Q: [f32; 3] = [0.5176, 0.7071, 1.9319];
+
+// anti-imaging/anti-aliasing cutoff relative to the base sample rate
+const CUTOFF_RATIO: f32 = 0.45;
+
+// group delay of the up and down filters below a few kHz, 2.26 samples at the base rate
+// whatever the rate is, since the cutoff follows it. rounded for reporting it as latency
+pub const LATENCY_SAMPLES: u32 = 2;
+
+// 2x stereo oversampler, zero-stuffing upsampler and decimating downsampler,
+// both band-limited with cascaded biquad lowpass filters
+pub struct Oversampler {
+    up_filters: [BiquadFilter; 3],
+    down_filters: [BiquadFilter; 3],
+}
+
+impl Oversampler {
+    pub fn new() -> Self {
+        Self {
+            up_filters: [BiquadFilter::new(); 3],
+            down_filters: [BiquadFilter::new(); 3],
+        }
+    }
+
+    // sample_rate is the base (not oversampled) sample rate
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let oversampled_rate = sample_rate * OVERSAMPLING as f32;
+        let cutoff = sample_rate * CUTOFF_RATIO;
+        for (i, q) in BUTTERWORTH_Q.iter().enumerate() {
+            self.up_filters[i].set_sample_rate(oversampled_rate);
+            self.up_filters[i].second_order_lpf_coefficients(oversampled_rate, cutoff, *q);
+            self.down_filters[i].set_sample_rate(oversampled_rate);
+            self.down_filters[i].second_order_lpf_coefficients(oversampled_rate, cutoff, *q);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for f in self.up_filters.iter_mut().chain(self.down_filters.iter_mut()) {
+            f.reset_filter();
+        }
+    }
+
+    pub fn upsample(&mut self, left: f32, right: f32) -> [(f32, f32); OVERSAMPLING] {
+        let mut frames = [(0.0, 0.0); OVERSAMPLING];
+        // zero-stuffing loses energy, make up for it on the non-zero sample
+        frames[0] = (left * OVERSAMPLING as f32, right * OVERSAMPLING as f32);
+
+        for frame in frames.iter_mut() {
+            for f in self.up_filters.iter_mut() {
+                frame.0 = f.process_left(frame.0);
+                frame.1 = f.process_right(frame.1);
+            }
+        }
+        frames
+    }
+
+    pub fn downsample(&mut self, frames: [(f32, f32); OVERSAMPLING]) -> (f32, f32) {
+        let mut out = (0.0, 0.0);
+        for (i, frame) in frames.iter().enumerate() {
+            let mut frame = *frame;
+            for f in self.down_filters.iter_mut() {
+                frame.0 = f.process_left(frame.0);
+                frame.1 = f.process_right(frame.1);
+            }
+            if i == 0 {
+                out = frame;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    // phase delay of a low sine through the up and down filters, in base rate samples
+    fn group_delay(sample_rate: f32, f: f64) -> f64 {
+        let mut oversampler = Oversampler::new();
+        oversampler.set_sample_rate(sample_rate);
+        let w = 2.0 * PI * f / sample_rate as f64;
+        let n = sample_rate as usize;
+        let (mut re, mut im) = (0.0, 0.0);
+        for i in 0..2 * n {
+            let phase = w * i as f64;
+            let frames = oversampler.upsample(phase.sin() as f32, 0.0);
+            let (y, _) = oversampler.downsample(frames);
+            if i >= n {
+                re += y as f64 * phase.sin();
+                im -= y as f64 * phase.cos();
+            }
+        }
+        im.atan2(re) / w
+    }
+
+    #[test]
+    fn latency_matches_the_group_delay() {
+        for sample_rate in [44100.0, 48000.0, 96000.0] {
+            for f in [50.0, 1000.0] {
+                let delay = group_delay(sample_rate, f);
+                assert!((delay - 2.26).abs() < 0.02, "{} Hz at {} Hz is delayed by {:.3}", f, sample_rate, delay);
+                assert_eq!(delay.round() as u32, LATENCY_SAMPLES);
+            }
+        }
+    }
+}
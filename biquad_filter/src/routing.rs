@@ -0,0 +1,54 @@
+use nih_plug::prelude::Enum;
+
+// how filter A and filter B are connected
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Routing {
+    Single,
+    Serial,
+    Parallel,
+    Split,
+}
+
+impl Enum for Routing {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "A Only",
+            "Serial (A > B)",
+            "Parallel (A + B)",
+            "Split (L > A, R > B)",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "single",
+            "serial",
+            "parallel",
+            "split",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            Routing::Single => 0,
+            Routing::Serial => 1,
+            Routing::Parallel => 2,
+            Routing::Split => 3,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Routing::Single,
+            1 => Routing::Serial,
+            2 => Routing::Parallel,
+            3 => Routing::Split,
+            _ => panic!("Invalid routing index."),
+        }
+    }
+}
+
+// balance from -1 (only A) to 1 (only B), both at full level in the middle
+pub fn balance_gains(balance: f32) -> (f32, f32) {
+    ((1.0 - balance).min(1.0), (1.0 + balance).min(1.0))
+}
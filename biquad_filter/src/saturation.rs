@@ -0,0 +1,80 @@
+use nih_plug::prelude::Enum;
+
+// level (about -12 dBFS) at which the auto gain matches the driven output to the input
+const REFERENCE_LEVEL: f32 = 0.25;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SaturationType {
+    Off,
+    Tanh,
+    SoftClip,
+    Diode,
+}
+
+impl Enum for SaturationType {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Off",
+            "Tanh",
+            "Soft Clip",
+            "Asymmetric Diode",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "off",
+            "tanh",
+            "soft_clip",
+            "diode",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            SaturationType::Off => 0,
+            SaturationType::Tanh => 1,
+            SaturationType::SoftClip => 2,
+            SaturationType::Diode => 3,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => SaturationType::Off,
+            1 => SaturationType::Tanh,
+            2 => SaturationType::SoftClip,
+            3 => SaturationType::Diode,
+            _ => panic!("Invalid saturation type index."),
+        }
+    }
+}
+
+// all curves have a slope of 1 around zero, so quiet signals pass unchanged
+pub fn saturate(saturation_type: SaturationType, x: f32) -> f32 {
+    match saturation_type {
+        SaturationType::Off => x,
+        SaturationType::Tanh => x.tanh(),
+        SaturationType::SoftClip => {
+            // cubic curve reaching +-1 with zero slope at +-1.5
+            let x = x.clamp(-1.5, 1.5);
+            x - (4.0 / 27.0) * x * x * x
+        },
+        SaturationType::Diode => {
+            // positive half saturates at 1, negative half at -0.5
+            if x >= 0.0 {
+                1.0 - (-x).exp()
+            } else {
+                -0.5 * (1.0 - (2.0 * x).exp())
+            }
+        },
+    }
+}
+
+// gain that brings a sine at the reference level back to its own level after drive and
+// saturation. both half waves are averaged, so the asymmetric diode is matched too
+pub fn makeup_gain(saturation_type: SaturationType, drive: f32) -> f32 {
+    let positive = saturate(saturation_type, REFERENCE_LEVEL * drive).abs();
+    let negative = saturate(saturation_type, -REFERENCE_LEVEL * drive).abs();
+    2.0 * REFERENCE_LEVEL / (positive + negative)
+}
@@ -0,0 +1,103 @@
+use std::f32::consts::PI;
+
+use crate::saturation::{self, SaturationType};
+
+// topology-preserving transform state variable filter (Andrew Simper's form).
+// the low-pass, band-pass, high-pass and notch outputs are available at once,
+// which makes it possible to blend between them without clicks
+#[derive(Clone, Copy)]
+pub struct StateVariableFilter {
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+
+    // integrator states
+    l_ic1eq: f32,
+    l_ic2eq: f32,
+    r_ic1eq: f32,
+    r_ic2eq: f32,
+
+    // 0..1 lp -> bp, 1..2 bp -> hp, 2..3 hp -> notch
+    morph: f32,
+    sample_rate: f32,
+    feedback_saturation: SaturationType,
+}
+
+impl StateVariableFilter {
+    pub fn new() -> Self {
+        Self {
+            k: 1.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            l_ic1eq: 0.0,
+            l_ic2eq: 0.0,
+            r_ic1eq: 0.0,
+            r_ic2eq: 0.0,
+            morph: 0.0,
+            sample_rate: 44100.0,
+            feedback_saturation: SaturationType::Off,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn set_feedback_saturation(&mut self, saturation_type: SaturationType) {
+        self.feedback_saturation = saturation_type;
+    }
+
+    pub fn reset_filter(&mut self) {
+        self.l_ic1eq = 0.0;
+        self.l_ic2eq = 0.0;
+        self.r_ic1eq = 0.0;
+        self.r_ic2eq = 0.0;
+    }
+
+    pub fn coefficients(&mut self, cutoff: f32, q: f32, morph: f32) {
+        let cutoff = cutoff.min(self.sample_rate * 0.49);
+        let g = (PI * cutoff / self.sample_rate).tan();
+        self.k = 1.0 / q;
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+        self.morph = morph.clamp(0.0, 3.0);
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let (y, ic1eq, ic2eq) = self.tick(x, self.l_ic1eq, self.l_ic2eq);
+        self.l_ic1eq = ic1eq;
+        self.l_ic2eq = ic2eq;
+        y
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let (y, ic1eq, ic2eq) = self.tick(x, self.r_ic1eq, self.r_ic2eq);
+        self.r_ic1eq = ic1eq;
+        self.r_ic2eq = ic2eq;
+        y
+    }
+
+    // one filtering step, returns the output and the new integrator states
+    fn tick(&self, x: f32, ic1eq: f32, ic2eq: f32) -> (f32, f32, f32) {
+        let v3 = x - ic2eq;
+        let v1 = self.a1 * ic1eq + self.a2 * v3;
+        let v1 = saturation::saturate(self.feedback_saturation, v1);
+        let v2 = ic2eq + self.a2 * ic1eq + self.a3 * v3;
+
+        let lp = v2;
+        // band-pass scaled to unity gain at the cutoff
+        let bp = self.k * v1;
+        let hp = x - self.k * v1 - v2;
+        let notch = lp + hp;
+
+        let outputs = [lp, bp, hp, notch];
+        let i = (self.morph.floor() as usize).min(2);
+        let t = self.morph - i as f32;
+        let y = outputs[i] * (1.0 - t) + outputs[i + 1] * t;
+
+        (y, 2.0 * v1 - ic1eq, 2.0 * v2 - ic2eq)
+    }
+}
@@ -0,0 +1,211 @@
+use nih_plug::prelude::Enum;
+
+use crate::filter::BiquadFilter;
+
+// number of stages in the emulated bucket-brigade chip. the clock needed for a delay
+// is stages / (2 * delay), so longer delays have a lower clock and less bandwidth
+const BBD_STAGES: f32 = 512.0;
+// the anti-alias and reconstruction filters sit a bit below the BBD nyquist
+const BANDWIDTH_RATIO: f32 = 0.4;
+// real units also band-limit short delays with fixed filters
+const MAX_CUTOFF: f32 = 10000.0;
+// the filters are only redesigned once the cutoff has moved by more than this fraction,
+// a sweeping delay would otherwise recalculate them every sample
+const CUTOFF_TOLERANCE: f32 = 0.01;
+// butterworth Qs of the two sections of a 4th order low-pass
+const FILTER_QS: [f32; 2] = [0.5412, 1.3066];
+
+// compander envelope time and the level it's centered around
+const ENVELOPE_MS: f32 = 10.0;
+const COMPANDER_REFERENCE: f32 = 0.25;
+const ENVELOPE_FLOOR: f32 = 0.0001;
+// hiss of the chip, added between the compressor and the expander
+const NOISE_LEVEL: f32 = 0.0005;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ChorusMode {
+    Digital,
+    Bbd,
+    JunoI,
+    JunoII,
+    JunoIAndII,
+    Doubler,
+}
+
+impl Enum for ChorusMode {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Digital",
+            "BBD",
+            "Juno I",
+            "Juno II",
+            "Juno I+II",
+            "Doubler",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "digital",
+            "bbd",
+            "juno_1",
+            "juno_2",
+            "juno_1_2",
+            "doubler",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            ChorusMode::Digital => 0,
+            ChorusMode::Bbd => 1,
+            ChorusMode::JunoI => 2,
+            ChorusMode::JunoII => 3,
+            ChorusMode::JunoIAndII => 4,
+            ChorusMode::Doubler => 5,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => ChorusMode::Digital,
+            1 => ChorusMode::Bbd,
+            2 => ChorusMode::JunoI,
+            3 => ChorusMode::JunoII,
+            4 => ChorusMode::JunoIAndII,
+            5 => ChorusMode::Doubler,
+            _ => panic!("Invalid chorus mode index."),
+        }
+    }
+}
+
+// fixed settings of the Juno-style modes, a single triangle LFO per channel with the
+// right channel inverted
+pub struct JunoPreset {
+    pub rate: f32,
+    pub delay_ms: f32,
+    pub depth_ms: f32,
+}
+
+impl ChorusMode {
+    pub fn is_bbd(self) -> bool {
+        matches!(self, ChorusMode::Bbd | ChorusMode::JunoI | ChorusMode::JunoII | ChorusMode::JunoIAndII)
+    }
+
+    pub fn juno_preset(self) -> Option<JunoPreset> {
+        match self {
+            // sweeps between about 1.7 and 5.3 ms
+            ChorusMode::JunoI => Some(JunoPreset { rate: 0.513, delay_ms: 3.5, depth_ms: 3.7 }),
+            ChorusMode::JunoII => Some(JunoPreset { rate: 0.863, delay_ms: 3.5, depth_ms: 3.7 }),
+            // both buttons pressed, a fast and shallow vibrato-like sweep
+            ChorusMode::JunoIAndII => Some(JunoPreset { rate: 9.75, delay_ms: 3.5, depth_ms: 0.4 }),
+            _ => None,
+        }
+    }
+}
+
+// bucket-brigade colouring of one delay line. the delay line itself stays in the
+// chorus, the signal is passed through encode() before it and decode() after it
+pub struct Bbd {
+    anti_alias: [BiquadFilter; 2],
+    reconstruction: [BiquadFilter; 2],
+    compressor_envelope: f32,
+    expander_envelope: f32,
+    envelope_coeff: f32,
+    noise_state: u32,
+    noise_seed: u32,
+    // cutoff the filters were last designed for
+    cutoff: f32,
+    sample_rate: f32,
+}
+
+impl Bbd {
+    pub fn new(sample_rate: f32, seed: u32) -> Self {
+        let mut bbd = Self {
+            anti_alias: [BiquadFilter::new(); 2],
+            reconstruction: [BiquadFilter::new(); 2],
+            compressor_envelope: COMPANDER_REFERENCE,
+            expander_envelope: COMPANDER_REFERENCE,
+            envelope_coeff: 0.0,
+            // xorshift needs a non-zero state
+            noise_state: seed | 1,
+            noise_seed: seed | 1,
+            cutoff: 0.0,
+            sample_rate,
+        };
+        bbd.set_sample_rate(sample_rate);
+        bbd
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.envelope_coeff = (-1.0 / (ENVELOPE_MS / 1000.0 * sample_rate)).exp();
+        // forces new coefficients on the next set_delay()
+        self.cutoff = 0.0;
+        for filter in self.anti_alias.iter_mut().chain(self.reconstruction.iter_mut()) {
+            filter.set_sample_rate(sample_rate);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for filter in self.anti_alias.iter_mut().chain(self.reconstruction.iter_mut()) {
+            filter.reset_filter();
+        }
+        self.compressor_envelope = COMPANDER_REFERENCE;
+        self.expander_envelope = COMPANDER_REFERENCE;
+        self.noise_state = self.noise_seed;
+        self.cutoff = 0.0;
+    }
+
+    // sets the filters for the clock that gives the current delay
+    pub fn set_delay(&mut self, delay_samples: f32) {
+        let delay_seconds = delay_samples.max(1.0) / self.sample_rate;
+        let clock = BBD_STAGES / (2.0 * delay_seconds);
+        let cutoff = (BANDWIDTH_RATIO * clock)
+            .min(MAX_CUTOFF)
+            .min(0.45 * self.sample_rate);
+        if (cutoff - self.cutoff).abs() <= CUTOFF_TOLERANCE * self.cutoff {
+            return;
+        }
+        self.cutoff = cutoff;
+
+        for (filter, q) in self.anti_alias.iter_mut().zip(FILTER_QS.iter()) {
+            filter.second_order_lpf_coefficients(self.sample_rate, cutoff, *q);
+        }
+        for (filter, q) in self.reconstruction.iter_mut().zip(FILTER_QS.iter()) {
+            filter.second_order_lpf_coefficients(self.sample_rate, cutoff, *q);
+        }
+    }
+
+    // 2:1 compressor, anti-alias filter and the soft clipping of the chip
+    pub fn encode(&mut self, x: f32) -> f32 {
+        self.compressor_envelope = x.abs() + self.envelope_coeff * (self.compressor_envelope - x.abs());
+        let compressed = x * (COMPANDER_REFERENCE / self.compressor_envelope.max(ENVELOPE_FLOOR)).sqrt();
+
+        let mut y = compressed;
+        for filter in self.anti_alias.iter_mut() {
+            y = filter.process_left(y);
+        }
+        y.tanh()
+    }
+
+    // hiss, reconstruction filter and the 1:2 expander, which also brings the hiss up and
+    // down with the signal
+    pub fn decode(&mut self, x: f32) -> f32 {
+        let mut y = x + NOISE_LEVEL * self.next_noise();
+        for filter in self.reconstruction.iter_mut() {
+            y = filter.process_left(y);
+        }
+
+        self.expander_envelope = y.abs() + self.envelope_coeff * (self.expander_envelope - y.abs());
+        y * self.expander_envelope / COMPANDER_REFERENCE
+    }
+
+    // white noise from -1 to 1, xorshift so the audio thread doesn't touch the rng
+    fn next_noise(&mut self) -> f32 {
+        self.noise_state ^= self.noise_state << 13;
+        self.noise_state ^= self.noise_state >> 17;
+        self.noise_state ^= self.noise_state << 5;
+        self.noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
@@ -1,8 +1,26 @@
 use std::collections::VecDeque;
+use std::f32::consts::PI;
 
 use rand::distributions::uniform::SampleRange;
 
-use crate::{delay::Delay, lfo};
+use crate::{bbd::{Bbd, ChorusMode}, delay::{Delay, Interpolation}, filter::FeedbackFilters, lfo::{self, SweepLaw}};
+
+pub const MAX_VOICES: usize = 8;
+
+// the doubler keeps its delay in the range where a copy reads as a second take rather
+// than a slapback or a chorus
+const DOUBLER_MIN_DELAY_MS: f32 = 10.0;
+const DOUBLER_MAX_DELAY_MS: f32 = 40.0;
+// fixed per-voice variance, so no two doubles are the same level or drift at the same speed
+// (and with it bend the pitch by the same amount)
+const DOUBLER_LEVELS: [f32; MAX_VOICES] = [1.0, 0.89, 0.94, 0.84, 0.97, 0.91, 0.86, 0.95];
+const DOUBLER_RATES: [f32; MAX_VOICES] = [1.0, 1.37, 0.71, 1.19, 0.83, 1.53, 0.62, 1.11];
+// voices fade in and out over this time when the voice count changes, and slide to their
+// new pan positions just as fast
+const VOICE_FADE_MS: f32 = 20.0;
+// fastest change of the delay spread of a voice, in samples per sample. the voices glide
+// to their new delays by less than a semitone when the voice count changes
+const MAX_SPREAD_SLEW: f32 = 0.05;
 
 pub struct Chorus {
     left_delays: Vec<Delay>,
@@ -21,42 +39,54 @@ pub struct Chorus {
     wet: f32,
     dry: f32,
 
-    // struct variables not to allocate in process()
-    lx: f32,
-    llfo1: f32,
-    llfo2: f32,
-    llfo3: f32,
-    loffset1: f32,
-    loffset2: f32,
-    loffset3: f32,
-    ldelayed_signal: f32,
-    left_out: f32,
-
-    rx: f32,
-    rlfo1: f32,
-    rlfo2: f32,
-    rlfo3: f32,
-    roffset1: f32,
-    roffset2: f32,
-    roffset3: f32,
-    rdelayed_signal: f32,
-    right_out: f32,
+    // number of voices in use, at most MAX_VOICES
+    voices: usize,
+    // fade gain of every voice, ramps to 1 for the voices in use and to 0 for the others
+    voice_gains: [f32; MAX_VOICES],
+    // 0 keeps every voice in its own channel, 1 spreads the voices from hard left to hard right
+    width: f32,
+    pan_positions: [f32; MAX_VOICES],
+    pan_targets: [f32; MAX_VOICES],
+    // every voice gets its own base delay and LFO rate, spread evenly around the set values.
+    // delay spread is in ms, rate spread is a fraction of the rate
+    delay_spread: f32,
+    rate_spread: f32,
+    // offset of every voice from the base delay, in samples
+    spread_offsets: [f32; MAX_VOICES],
+    spread_targets: [f32; MAX_VOICES],
+    sweep_law: SweepLaw,
+    // bucket-brigade colouring of every delay line, used in all modes but Digital
+    mode: ChorusMode,
+    left_bbds: Vec<Bbd>,
+    right_bbds: Vec<Bbd>,
+    // random delay drift of every voice in the Doubler mode
+    drifts: Vec<lfo::Drift>,
+    // tone control inside the feedback loop
+    feedback_filters: FeedbackFilters,
 }
 
 impl Chorus {
     pub fn new(sample_rate: f32, delay_ms: f32, feedback: f32, depth: f32, rate: f32, wet: f32, dry: f32) -> Self {
-        let mut left_delays: Vec<Delay> = Vec::with_capacity(5);
-        let mut right_delays: Vec<Delay> = Vec::with_capacity(5);
-        let mut left_lfos: Vec<lfo::LFO> = Vec::with_capacity(5);
-        let mut right_lfos: Vec<lfo::LFO> = Vec::with_capacity(5);
+        let mut left_delays: Vec<Delay> = Vec::with_capacity(MAX_VOICES);
+        let mut right_delays: Vec<Delay> = Vec::with_capacity(MAX_VOICES);
+        let mut left_lfos: Vec<lfo::LFO> = Vec::with_capacity(MAX_VOICES);
+        let mut right_lfos: Vec<lfo::LFO> = Vec::with_capacity(MAX_VOICES);
 
         let delay_samples_f32: f32 = (delay_ms as f32 / 1000.0) * sample_rate as f32;
 
-        for i in 0..5 {
+        let mut left_bbds: Vec<Bbd> = Vec::with_capacity(MAX_VOICES);
+        let mut right_bbds: Vec<Bbd> = Vec::with_capacity(MAX_VOICES);
+        let mut drifts: Vec<lfo::Drift> = Vec::with_capacity(MAX_VOICES);
+
+        for i in 0..MAX_VOICES {
             left_delays.push(Delay::new(sample_rate as usize, delay_samples_f32, 0.0));
             right_delays.push(Delay::new(sample_rate as usize, delay_samples_f32, 0.0));
-            left_lfos.push(lfo::LFO::new_random_phase(sample_rate, rate));
-            right_lfos.push(lfo::LFO::new_random_phase(sample_rate, rate));
+            left_lfos.push(lfo::LFO::new(sample_rate, rate));
+            right_lfos.push(lfo::LFO::new(sample_rate, rate));
+            // every chip gets its own hiss
+            left_bbds.push(Bbd::new(sample_rate, 2 * i as u32 + 1));
+            right_bbds.push(Bbd::new(sample_rate, 2 * i as u32 + 2));
+            drifts.push(lfo::Drift::new(sample_rate, rate * DOUBLER_RATES[i], i as u32 + 1));
         }
 
         let mut left_feedback_buffer: Box<VecDeque<f32>> 
@@ -68,7 +98,7 @@ impl Chorus {
             right_feedback_buffer.push_front(0.0);
         }
 
-        Self {
+        let mut chorus = Self {
             left_delays,
             right_delays,
             left_lfos,
@@ -84,43 +114,100 @@ impl Chorus {
             mono: false,
             delay_ms,
             delay_samples_f32,
-            lx: 0.0,
-            llfo1: 0.0,
-            llfo2: 0.0,
-            llfo3: 0.0,
-            loffset1: 0.0,
-            loffset2: 0.0,
-            loffset3: 0.0,
-            ldelayed_signal: 0.0,
-            left_out: 0.0,
-            rx: 0.0,
-            rlfo1: 0.0,
-            rlfo2: 0.0,
-            rlfo3: 0.0,
-            roffset1: 0.0,
-            roffset2: 0.0,
-            roffset3: 0.0,
-            rdelayed_signal: 0.0,
-            right_out: 0.0,
-        }
-    }
-
-    pub fn set_params(&mut self, sample_rate: f32, delay: f32, feedback: f32, depth: f32, rate: f32, mix: f32, mono: bool) {
+            voices: 0,
+            voice_gains: [0.0; MAX_VOICES],
+            width: 0.0,
+            pan_positions: [0.0; MAX_VOICES],
+            pan_targets: [0.0; MAX_VOICES],
+            delay_spread: 0.0,
+            rate_spread: 0.0,
+            spread_offsets: [0.0; MAX_VOICES],
+            spread_targets: [0.0; MAX_VOICES],
+            sweep_law: SweepLaw::Linear,
+            mode: ChorusMode::Digital,
+            left_bbds,
+            right_bbds,
+            drifts,
+            feedback_filters: FeedbackFilters::new(),
+        };
+        chorus.set_voices(3);
+        chorus.snap_voices();
+        chorus
+    }
+
+    // voices are placed evenly from left to right. only the LFOs of newly enabled voices
+    // get a new phase, the running ones keep theirs so their delays don't jump
+    fn set_voices(&mut self, voices: usize) {
+        if voices > self.voices {
+            self.spread_phases(self.voices, voices);
+        }
+        self.voices = voices;
+        for i in 0..voices {
+            self.pan_targets[i] = if voices > 1 {
+                -1.0 + 2.0 * i as f32 / (voices - 1) as f32
+            } else {
+                0.0
+            };
+        }
+    }
+
+    // places the LFOs of the voices from first on evenly after voice 0, as if there were
+    // `voices` of them. the right channel sits half a step behind the left one
+    fn spread_phases(&mut self, first: usize, voices: usize) {
+        let start = self.left_lfos[0].phase();
+        let step = 2.0 * PI / voices as f32;
+        for i in first..voices {
+            self.left_lfos[i].set_phase(start + i as f32 * step);
+            self.right_lfos[i].set_phase(start + (i as f32 + 0.5) * step);
+        }
+    }
+
+    // skips the fades and glides
+    fn snap_voices(&mut self) {
+        for i in 0..MAX_VOICES {
+            self.voice_gains[i] = if i < self.voices { 1.0 } else { 0.0 };
+            self.pan_positions[i] = self.pan_targets[i];
+            self.spread_offsets[i] = self.spread_targets[i];
+        }
+    }
+
+    pub fn set_params(&mut self, sample_rate: f32, delay: f32, feedback: f32, depth: f32, rate: f32, mix: f32, mono: bool, voices: usize, width: f32, mode: ChorusMode, interpolation: Interpolation) {
         // resize all buffers relying on sample rate
         self.sample_rate = sample_rate;
+
+        // the Juno modes replace the delay, depth, rate and voice settings, the doubler
+        // limits the delay and has no feedback
+        let (delay, feedback, depth, rate, voices) = match mode.juno_preset() {
+            Some(preset) => (preset.delay_ms, 0.0, preset.depth_ms, preset.rate, 1),
+            None if mode == ChorusMode::Doubler => {
+                (delay.clamp(DOUBLER_MIN_DELAY_MS, DOUBLER_MAX_DELAY_MS), 0.0, depth, rate, voices)
+            }
+            None => (delay, feedback, depth, rate, voices),
+        };
+        if mode.is_bbd() && !self.mode.is_bbd() {
+            for bbd in self.left_bbds.iter_mut().chain(self.right_bbds.iter_mut()) {
+                bbd.reset();
+            }
+        }
+        self.mode = mode;
     
         for (lfol, lfor) in self.left_lfos.iter_mut().zip(self.right_lfos.iter_mut()) {
             lfol.sample_rate = sample_rate;
             lfor.sample_rate = sample_rate;
         }
+        for drift in self.drifts.iter_mut() {
+            drift.sample_rate = sample_rate;
+        }
 
         let delay_samples_f32: f32 = (delay as f32 / 1000.0) * self.sample_rate as f32;
 
         for d in self.left_delays.iter_mut() {
             d.delay = delay_samples_f32;
+            d.set_interpolation(interpolation);
         }
         for d in self.right_delays.iter_mut() {
             d.delay = delay_samples_f32;
+            d.set_interpolation(interpolation);
         }
 
         self.feedback = feedback;
@@ -128,23 +215,87 @@ impl Chorus {
         self.depth = depth;
         self.calc_depth = depth / 1000.0 * self.sample_rate;
 
-        for (lfol, lfor) in self.left_lfos.iter_mut().zip(self.right_lfos.iter_mut()) {
-            lfol.rate = rate;
-            lfor.rate = rate;
-        }
-
         self.wet = mix;
         self.dry = 1.0 - mix;
         self.mono = mono;
+        let voices = voices.clamp(1, MAX_VOICES);
+        if voices != self.voices {
+            self.set_voices(voices);
+        }
+
+        // the Juno modes have a single voice, so the spread doesn't change them
+        for i in 0..MAX_VOICES {
+            let position = self.spread_position(i);
+            let voice_rate = rate * (1.0 + self.rate_spread * position);
+            self.left_lfos[i].rate = voice_rate;
+            self.right_lfos[i].rate = voice_rate;
+            self.drifts[i].rate = voice_rate * DOUBLER_RATES[i];
+            self.spread_targets[i] = self.delay_spread * position / 1000.0 * self.sample_rate;
+        }
+        self.width = width;
         self.delay_ms = delay;
         self.delay_samples_f32 = delay_samples_f32;
     }
 
+    // the Juno modes always sweep linearly, like the originals
+    pub fn set_sweep_law(&mut self, sweep_law: SweepLaw) {
+        self.sweep_law = sweep_law;
+    }
+
+    // takes effect on the next set_params() call
+    pub fn set_spread(&mut self, delay_spread: f32, rate_spread: f32) {
+        self.delay_spread = delay_spread;
+        self.rate_spread = rate_spread;
+    }
+
+    // where a voice sits in the spread, from -0.5 to 0.5. unused voices stay at the edge
+    fn spread_position(&self, voice: usize) -> f32 {
+        if self.voices > 1 {
+            (voice as f32 / (self.voices - 1) as f32).min(1.0) - 0.5
+        } else {
+            0.0
+        }
+    }
+
+    pub fn set_feedback_filters(&mut self, low_pass: f32, high_pass: f32) {
+        self.feedback_filters.set_cutoffs(low_pass, high_pass);
+    }
+
+    // clears the delay lines, feedback and filter state and puts the LFOs back where
+    // they start, so playback from the same point always sounds the same
+    pub fn reset(&mut self) {
+        for (dl, dr) in self.left_delays.iter_mut().zip(self.right_delays.iter_mut()) {
+            dl.reset();
+            dr.reset();
+        }
+        for bbd in self.left_bbds.iter_mut().chain(self.right_bbds.iter_mut()) {
+            bbd.reset();
+        }
+        for lfo in self.left_lfos.iter_mut().chain(self.right_lfos.iter_mut()) {
+            lfo.reset();
+        }
+        self.spread_phases(0, self.voices);
+        self.snap_voices();
+        for drift in self.drifts.iter_mut() {
+            drift.reset();
+        }
+
+        self.left_feedback_buffer.iter_mut().for_each(|x| *x = 0.0);
+        self.right_feedback_buffer.iter_mut().for_each(|x| *x = 0.0);
+        self.feedback_filters.reset();
+    }
+
     pub fn resize_buffers(&mut self, sample_rate: f32) {
         for (dl, dr) in self.left_delays.iter_mut().zip(self.right_delays.iter_mut()) {
             dl.resize_buffers(sample_rate as usize);
             dr.resize_buffers(sample_rate as usize);
         }
+        for bbd in self.left_bbds.iter_mut().chain(self.right_bbds.iter_mut()) {
+            bbd.set_sample_rate(sample_rate);
+        }
+
+        self.sample_rate = sample_rate;
+        self.feedback_filters.set_sample_rate(sample_rate);
 
         self.left_feedback_buffer = Box::new(VecDeque::with_capacity(sample_rate as usize));
         self.right_feedback_buffer = Box::new(VecDeque::with_capacity(sample_rate as usize));
@@ -154,73 +305,121 @@ impl Chorus {
         }
     }
 
+    // voices are mostly uncorrelated, so the wet signal is scaled by 1/sqrt(voices) to keep
+    // the loudness the same. the feedback path is scaled by 1/voices, so coherent voices
+    // (no depth) can't push the feedback loop above unity gain. fading voices count partly
+    fn wet_gain(&self) -> f32 {
+        1.0 / self.active_voices().sqrt()
+    }
 
+    fn feedback_gain(&self) -> f32 {
+        1.0 / self.active_voices()
+    }
 
-    pub fn process_left(&mut self, x: f32) -> f32 {
-        self.lx = x + self.wet * self.feedback * self.left_feedback_buffer.get(0).unwrap();
-
-        self.llfo1 = self.left_lfos[0].next_value();
-        self.llfo2 = self.left_lfos[1].next_value();
-        self.llfo3 = self.left_lfos[2].next_value();
+    // never below 1, voice 0 is always in use
+    fn active_voices(&self) -> f32 {
+        self.voice_gains.iter().sum::<f32>().max(1.0)
+    }
 
-        self.loffset1 = (self.llfo1 * self.calc_depth / 2.0).clamp(-(self.delay_samples_f32) + 1.0 , self.delay_samples_f32 - 1.0);
-        self.loffset2 = (self.llfo2 * self.calc_depth / 2.0).clamp(-(self.delay_samples_f32) + 1.0 , self.delay_samples_f32 - 1.0);
-        self.loffset3 = (self.llfo3 * self.calc_depth / 2.0).clamp(-(self.delay_samples_f32) + 1.0 , self.delay_samples_f32 - 1.0);
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        if self.mode == ChorusMode::Doubler {
+            return self.process_doubler(left, right);
+        }
 
-        self.ldelayed_signal = 0.0;
-        self.ldelayed_signal += self.left_delays[0].process_sample(self.lx, self.delay_samples_f32 + self.loffset1);
-        self.ldelayed_signal += self.left_delays[1].process_sample(self.lx, self.delay_samples_f32 + self.loffset2);
-        self.ldelayed_signal += self.left_delays[2].process_sample(self.lx, self.delay_samples_f32 + self.loffset3);
+        let lx = left + self.wet * self.feedback * self.left_feedback_buffer[0];
+        let rx = right + self.wet * self.feedback * self.right_feedback_buffer[0];
+
+        let mut left_sum = 0.0;
+        let mut right_sum = 0.0;
+        let mut left_wet = 0.0;
+        let mut right_wet = 0.0;
+        for i in 0..MAX_VOICES {
+            // voices that are off only keep their delay lines current, so they hold recent
+            // audio when they are turned on again
+            if self.voice_gains[i] == 0.0 {
+                self.left_delays[i].write(lx);
+                self.right_delays[i].write(rx);
+                continue;
+            }
+
+            let (left_lfo, right_lfo) = if self.mode.juno_preset().is_some() {
+                let lfo = self.left_lfos[i].next_value_triangle();
+                (lfo, -lfo)
+            } else {
+                let lfo = self.left_lfos[i].next_value();
+                // mono, meaning mono modulation
+                (lfo, if self.mono { lfo } else { self.right_lfos[i].next_value() })
+            };
+
+            let left_delay = self.modulated_delay(i, left_lfo);
+            let right_delay = self.modulated_delay(i, right_lfo);
+            let (yl, yr) = if self.mode.is_bbd() {
+                let (left_bbd, right_bbd) = (&mut self.left_bbds[i], &mut self.right_bbds[i]);
+                left_bbd.set_delay(left_delay);
+                right_bbd.set_delay(right_delay);
+                let yl = self.left_delays[i].process_sample(left_bbd.encode(lx), left_delay);
+                let yr = self.right_delays[i].process_sample(right_bbd.encode(rx), right_delay);
+                (left_bbd.decode(yl), right_bbd.decode(yr))
+            } else {
+                (
+                    self.left_delays[i].process_sample(lx, left_delay),
+                    self.right_delays[i].process_sample(rx, right_delay),
+                )
+            };
+            let gain = self.voice_gains[i];
+            left_sum += gain * yl;
+            right_sum += gain * yr;
+            let (l, r) = pan(gain * yl, gain * yr, self.width * self.pan_positions[i]);
+            left_wet += l;
+            right_wet += r;
+        }
 
-        //self.left_feedback_buffer.rotate_right(1);
-        self.left_feedback_buffer[0] = self.ldelayed_signal / 3.0;
+        // feedback stays in its own channel
+        self.left_feedback_buffer[0] = self.feedback_filters.process_left(left_sum * self.feedback_gain());
+        self.right_feedback_buffer[0] = self.feedback_filters.process_right(right_sum * self.feedback_gain());
 
-        self.left_out = 
-        self.dry * x 
-        + self.wet * 1.0/3.0 * self.ldelayed_signal;
+        let mut left_out = self.dry * left + self.wet * self.wet_gain() * left_wet;
+        let mut right_out = self.dry * right + self.wet * self.wet_gain() * right_wet;
 
         if self.wet + self.dry > 1.0 {
-            self.left_out /= self.wet + self.dry;
+            left_out /= self.wet + self.dry;
+            right_out /= self.wet + self.dry;
         }
 
-        self.left_out
+        (left_out, right_out)
     }
 
-    pub fn process_right(&mut self, x: f32) -> f32 {
-        self.rx = x + self.wet * self.feedback * self.right_feedback_buffer.get(0).unwrap();
-
-        // mono, meaning mono modulation
-        if self.mono {
-            self.rlfo1 = self.left_lfos[0].next_value();
-            self.rlfo2 = self.left_lfos[1].next_value();
-            self.rlfo3 = self.left_lfos[2].next_value();
-        } else {
-            self.rlfo1 = self.right_lfos[0].next_value();
-            self.rlfo2 = self.right_lfos[1].next_value();
-            self.rlfo3 = self.right_lfos[2].next_value();
+    // automatic double tracking: the original goes hard left and the doubles hard right,
+    // the mix blends the right channel from the original to the doubles
+    fn process_doubler(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let x = (left + right) / 2.0;
+
+        let mut double = 0.0;
+        for i in 0..MAX_VOICES {
+            if self.voice_gains[i] == 0.0 {
+                self.left_delays[i].write(x);
+                self.right_delays[i].write(right);
+                continue;
+            }
+            let delay = self.modulated_delay(i, self.drifts[i].next_value());
+            let y = self.left_delays[i].process_sample(x, delay);
+            // keeps the right delay lines current for switching back to the other modes
+            self.right_delays[i].process_sample(right, delay);
+            double += self.voice_gains[i] * DOUBLER_LEVELS[i] * y;
         }
-        
 
-        self.roffset1 = (self.rlfo1 * self.calc_depth / 2.0).clamp(-(self.delay_samples_f32) + 1.0 , self.delay_samples_f32 - 1.0);
-        self.roffset2 = (self.rlfo2 * self.calc_depth / 2.0).clamp(-(self.delay_samples_f32) + 1.0 , self.delay_samples_f32 - 1.0);
-        self.roffset3 = (self.rlfo3 * self.calc_depth / 2.0).clamp(-(self.delay_samples_f32) + 1.0 , self.delay_samples_f32 - 1.0);
+        self.left_feedback_buffer[0] = 0.0;
+        self.right_feedback_buffer[0] = 0.0;
 
-        self.rdelayed_signal = 0.0;
-        self.rdelayed_signal += self.right_delays[0].process_sample(self.rx, self.delay_samples_f32 + self.roffset1);
-        self.rdelayed_signal += self.right_delays[1].process_sample(self.rx, self.delay_samples_f32 + self.roffset2);
-        self.rdelayed_signal += self.right_delays[2].process_sample(self.rx, self.delay_samples_f32 + self.roffset3);
-
-        //self.right_feedback_buffer.rotate_right(1);
-        self.right_feedback_buffer[0] = self.rdelayed_signal / 3.0;
-
-        self.right_out = self.dry * x 
-        + self.wet * 1.0/3.0 * self.rdelayed_signal;
-
-        if self.wet + self.dry > 1.0 {
-            self.right_out /= self.wet + self.dry;
-        }
+        (x, self.dry * x + self.wet * self.wet_gain() * double)
+    }
 
-        self.right_out
+    fn modulated_delay(&self, voice: usize, lfo: f32) -> f32 {
+        let base = (self.delay_samples_f32 + self.spread_offsets[voice]).max(1.0);
+        let sweep_law = if self.mode.juno_preset().is_some() { SweepLaw::Linear } else { self.sweep_law };
+        let min_delay = (base - self.calc_depth / 2.0).max(1.0);
+        let max_delay = base + (base - min_delay).min(self.calc_depth / 2.0);
+        sweep_law.map(lfo, min_delay, max_delay)
     }
 
     pub fn update_modulators(&mut self) {
@@ -230,5 +429,34 @@ impl Chorus {
         for lfo in self.right_lfos.iter_mut() {
             lfo.update_lfo();
         }
+        for drift in self.drifts.iter_mut() {
+            drift.update_drift();
+        }
+
+        let fade_step = 1000.0 / (VOICE_FADE_MS * self.sample_rate);
+        for i in 0..MAX_VOICES {
+            let target = if i < self.voices { 1.0 } else { 0.0 };
+            self.voice_gains[i] = ramp(self.voice_gains[i], target, fade_step);
+            self.pan_positions[i] = ramp(self.pan_positions[i], self.pan_targets[i], 2.0 * fade_step);
+            self.spread_offsets[i] = ramp(self.spread_offsets[i], self.spread_targets[i], MAX_SPREAD_SLEW);
+        }
+    }
+}
+
+// moves current towards target by at most step
+fn ramp(current: f32, target: f32, step: f32) -> f32 {
+    if current < target {
+        (current + step).min(target)
+    } else {
+        (current - step).max(target)
     }
-}
\ No newline at end of file
+}
+
+// moves a stereo voice towards one side, at -1 or 1 both of its channels end up on that side
+fn pan(left: f32, right: f32, position: f32) -> (f32, f32) {
+    if position >= 0.0 {
+        (left * (1.0 - position), right + left * position)
+    } else {
+        (left - right * position, right * (1.0 + position))
+    }
+}
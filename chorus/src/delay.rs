@@ -1,7 +1,79 @@
 use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use nih_plug::prelude::Enum;
 
 const MAX_DELAY: usize = 1; // 3 seconds at 44100Hz
 
+// number of taps of the windowed sinc interpolator
+const SINC_TAPS: usize = 8;
+
+// how the delay line reads between two samples
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    CubicHermite,
+    Lagrange,
+    ThiranAllpass,
+    WindowedSinc,
+}
+
+impl Enum for Interpolation {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Linear",
+            "Cubic Hermite",
+            "Lagrange (4-point)",
+            "Thiran Allpass",
+            "Windowed Sinc",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "linear",
+            "hermite",
+            "lagrange",
+            "thiran",
+            "sinc",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            Interpolation::Linear => 0,
+            Interpolation::CubicHermite => 1,
+            Interpolation::Lagrange => 2,
+            Interpolation::ThiranAllpass => 3,
+            Interpolation::WindowedSinc => 4,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Interpolation::Linear,
+            1 => Interpolation::CubicHermite,
+            2 => Interpolation::Lagrange,
+            3 => Interpolation::ThiranAllpass,
+            4 => Interpolation::WindowedSinc,
+            _ => panic!("Invalid interpolation index."),
+        }
+    }
+}
+
+impl Interpolation {
+    // the interpolators read samples after the read position, so the delay can't be
+    // shorter than that or they would read samples that haven't been written yet
+    fn min_delay(self) -> f32 {
+        match self {
+            Interpolation::Linear => 0.0,
+            Interpolation::CubicHermite | Interpolation::Lagrange => 2.0,
+            Interpolation::ThiranAllpass => 0.5,
+            Interpolation::WindowedSinc => (SINC_TAPS / 2) as f32,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Delay {
     x_buffer: Vec<f32>,
@@ -18,6 +90,10 @@ pub struct Delay {
     read_index_floor: usize,
     read_index_frac: f32,
     output: f32,
+
+    interpolation: Interpolation,
+    // last output of the thiran allpass
+    allpass_state: f32,
 }
 
 impl Delay {
@@ -47,6 +123,8 @@ impl Delay {
             read_index_floor: 0,
             read_index_frac: 0.0,
             output: 0.0,
+            interpolation: Interpolation::Linear,
+            allpass_state: 0.0,
         }
     }
 
@@ -57,16 +135,46 @@ impl Delay {
         self.buffer_size = buffer_size;
     }
 
+    // clears the buffers without reallocating, safe to call from the audio thread
+    pub fn reset(&mut self) {
+        self.x_buffer.fill(0.0);
+        self.y_buffer.fill(0.0);
+        self.write_index = 0;
+        self.output = 0.0;
+        self.allpass_state = 0.0;
+    }
+
     // y(n) = x(n - delay) + fb * y(n - delay)
     pub fn process_sample(&mut self, x: f32, delay_samples_f32: f32) -> f32 {
         self.x_buffer[self.write_index] = x;
 
+        let delay_samples_f32 = delay_samples_f32.max(self.interpolation.min_delay());
         self.read_index = ((self.write_index + self.buffer_size) as f32 - delay_samples_f32) % self.buffer_size as f32;
         self.read_index_floor = self.read_index.floor() as usize;
         self.read_index_frac = self.read_index.fract();
 
-        self.output = self.x_buffer[self.read_index_floor] * (1.0 - self.read_index_frac)
-            + self.x_buffer[(self.read_index_floor + 1) % self.buffer_size] * self.read_index_frac;
+        let t = self.read_index_frac;
+        self.output = match self.interpolation {
+            Interpolation::Linear => {
+                self.sample(0) * (1.0 - t) + self.sample(1) * t
+            }
+            Interpolation::CubicHermite => {
+                let (xm1, x0, x1, x2) = (self.sample(-1), self.sample(0), self.sample(1), self.sample(2));
+                let c1 = 0.5 * (x1 - xm1);
+                let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+                let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+                ((c3 * t + c2) * t + c1) * t + x0
+            }
+            Interpolation::Lagrange => {
+                let (xm1, x0, x1, x2) = (self.sample(-1), self.sample(0), self.sample(1), self.sample(2));
+                -t * (t - 1.0) * (t - 2.0) / 6.0 * xm1
+                    + (t + 1.0) * (t - 1.0) * (t - 2.0) / 2.0 * x0
+                    - (t + 1.0) * t * (t - 2.0) / 2.0 * x1
+                    + (t + 1.0) * t * (t - 1.0) / 6.0 * x2
+            }
+            Interpolation::ThiranAllpass => self.thiran(delay_samples_f32),
+            Interpolation::WindowedSinc => self.windowed_sinc(t),
+        };
 
         self.y_buffer[self.write_index] = self.output;
 
@@ -75,6 +183,63 @@ impl Delay {
         self.output
     }
 
+    // only stores the input, for delay lines whose output isn't needed right now
+    pub fn write(&mut self, x: f32) {
+        self.x_buffer[self.write_index] = x;
+        self.y_buffer[self.write_index] = 0.0;
+        self.write_index = (self.write_index + 1) % self.buffer_size;
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    // sample at an offset from the integer part of the read index
+    fn sample(&self, offset: isize) -> f32 {
+        let index = (self.read_index_floor as isize + offset).rem_euclid(self.buffer_size as isize);
+        self.x_buffer[index as usize]
+    }
+
+    // first order allpass for the fractional part, which is kept between 0.5 and 1.5
+    // where the allpass delay is accurate. flat magnitude, but fast delay changes ring a bit
+    fn thiran(&mut self, delay: f32) -> f32 {
+        let integer = (delay - 0.5).floor();
+        let fraction = delay - integer;
+        let eta = (1.0 - fraction) / (1.0 + fraction);
+
+        let index = (self.write_index + self.buffer_size - integer as usize) % self.buffer_size;
+        let current = self.x_buffer[index];
+        let previous = self.x_buffer[(index + self.buffer_size - 1) % self.buffer_size];
+
+        self.allpass_state = eta * current + previous - eta * self.allpass_state;
+        self.allpass_state
+    }
+
+    // blackman windowed sinc, normalized so DC passes at unity gain
+    fn windowed_sinc(&self, t: f32) -> f32 {
+        let half = (SINC_TAPS / 2) as isize;
+        // sin(pi * (k - t)) only changes sign between the taps
+        let sin_t = (PI * t).sin();
+
+        let mut sum = 0.0;
+        let mut weight_sum = 0.0;
+        for k in (1 - half)..=half {
+            let distance = k as f32 - t;
+            let sinc = if distance.abs() < 1e-6 {
+                1.0
+            } else {
+                let sign = if k % 2 == 0 { -1.0 } else { 1.0 };
+                sign * sin_t / (PI * distance)
+            };
+            let phase = 2.0 * PI * distance / SINC_TAPS as f32;
+            let window = 0.42 + 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            let weight = sinc * window;
+            sum += weight * self.sample(k);
+            weight_sum += weight;
+        }
+        sum / weight_sum
+    }
+
     fn calculate_read_index(&self, delay: f32) -> f32 {
         let delay_samples = delay;
         let read_index = self.write_index as f32 - delay_samples;
@@ -86,4 +251,115 @@ impl Delay {
             read_index
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    const INTERPOLATIONS: [Interpolation; 5] = [
+        Interpolation::Linear,
+        Interpolation::CubicHermite,
+        Interpolation::Lagrange,
+        Interpolation::ThiranAllpass,
+        Interpolation::WindowedSinc,
+    ];
+    const FRACTIONS: [f32; 3] = [0.25, 0.5, 0.75];
+    const FREQUENCIES: [f32; 4] = [0.05, 0.1, 0.2, 0.4];
+    const DELAY: f32 = 10.0;
+    const SETTLE: usize = 256;
+    // a whole number of cycles at every test frequency
+    const LENGTH: usize = 4000;
+
+    fn name(interpolation: Interpolation) -> &'static str {
+        Interpolation::variants()[interpolation.to_index()]
+    }
+
+    // magnitude and phase delay error in samples of a delay line read at a fixed delay, for a
+    // sine at f cycles per sample, measured by correlating the output with the input
+    fn response(interpolation: Interpolation, delay: f32, f: f32) -> (f64, f64) {
+        let mut line = Delay::new(1000, 0.0, 0.0);
+        line.set_interpolation(interpolation);
+        let w = TAU * f as f64;
+        let (mut re, mut im) = (0.0, 0.0);
+        for n in 0..SETTLE + LENGTH {
+            let phase = w * n as f64;
+            let y = line.process_sample(phase.sin() as f32, delay) as f64;
+            if n >= SETTLE {
+                re += y * phase.sin();
+                im -= y * phase.cos();
+            }
+        }
+        let magnitude = 2.0 * re.hypot(im) / LENGTH as f64;
+        let error = (im.atan2(re) - w * delay as f64 + TAU / 2.0).rem_euclid(TAU) - TAU / 2.0;
+        (magnitude, error / w)
+    }
+
+    #[test]
+    fn integer_delays_are_exact() {
+        for interpolation in INTERPOLATIONS {
+            for f in FREQUENCIES {
+                let (magnitude, error) = response(interpolation, DELAY, f);
+                assert!((magnitude - 1.0).abs() < 1e-3, "{} at {}: |H| = {:.4}", name(interpolation), f, magnitude);
+                assert!(error.abs() < 1e-3, "{} at {}: delay off by {:.4}", name(interpolation), f, error);
+            }
+        }
+    }
+
+    #[test]
+    fn low_frequencies_pass_at_the_fractional_delay() {
+        for interpolation in INTERPOLATIONS {
+            for t in FRACTIONS {
+                let (magnitude, error) = response(interpolation, DELAY + t, 0.05);
+                assert!(magnitude > 0.98 && magnitude < 1.001, "{} at +{}: |H| = {:.4}", name(interpolation), t, magnitude);
+                assert!(error.abs() < 0.01, "{} at +{}: delay off by {:.4}", name(interpolation), t, error);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_follows_its_analytic_response() {
+        for t in FRACTIONS {
+            for f in FREQUENCIES {
+                let (magnitude, _) = response(Interpolation::Linear, DELAY + t, f);
+                let (w, t) = (TAU * f as f64, t as f64);
+                let expected = ((1.0 - t) + t * w.cos()).hypot(t * w.sin());
+                assert!((magnitude - expected).abs() < 1e-3, "+{} at {}: |H| = {:.4}, expected {:.4}", t, f, magnitude, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn cubics_roll_off_less_than_linear() {
+        for interpolation in [Interpolation::CubicHermite, Interpolation::Lagrange] {
+            for t in FRACTIONS {
+                for f in [0.1, 0.2] {
+                    let (linear, _) = response(Interpolation::Linear, DELAY + t, f);
+                    let (magnitude, _) = response(interpolation, DELAY + t, f);
+                    assert!(magnitude > linear && magnitude > 0.94, "{} at +{}, {}: |H| = {:.4}, linear {:.4}", name(interpolation), t, f, magnitude, linear);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn thiran_is_all_pass() {
+        for t in FRACTIONS {
+            for f in FREQUENCIES {
+                let (magnitude, _) = response(Interpolation::ThiranAllpass, DELAY + t, f);
+                assert!((magnitude - 1.0).abs() < 1e-3, "+{} at {}: |H| = {:.4}", t, f, magnitude);
+            }
+        }
+    }
+
+    #[test]
+    fn sinc_is_flat_to_a_fifth_of_the_sample_rate() {
+        for t in FRACTIONS {
+            for f in [0.05, 0.1, 0.2] {
+                let (magnitude, error) = response(Interpolation::WindowedSinc, DELAY + t, f);
+                assert!((magnitude - 1.0).abs() < 0.005, "+{} at {}: |H| = {:.4}", t, f, magnitude);
+                assert!(error.abs() < 0.005, "+{} at {}: delay off by {:.4}", t, f, error);
+            }
+        }
+    }
+}
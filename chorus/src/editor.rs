@@ -66,7 +66,7 @@ struct Data {
 impl Model for Data {}
 
 pub(crate) fn default_state() -> Arc<ViziaState> {
-    ViziaState::new(|| (350, 350))
+    ViziaState::new(|| (350, 710))
 }
 
 pub(crate) fn create(
@@ -110,6 +110,21 @@ pub(crate) fn create(
                             .bottom(Percentage(51.0));
     
                     }).col_between(Pixels(15.0));
+                    HStack::new(cx, |cx| {
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.voices, false);
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.width, false);
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.mode, false);
+                    }).col_between(Pixels(15.0));
+                    HStack::new(cx, |cx| {
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.quality, false);
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.feedback_lpf, false);
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.feedback_hpf, false);
+                    }).col_between(Pixels(15.0));
+                    HStack::new(cx, |cx| {
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.delay_spread, false);
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.rate_spread, false);
+                        ParamKnob::new(cx, Data::chorus_data, |params| &params.sweep_law, false);
+                    }).col_between(Pixels(15.0));
 
                 }).col_between(Pixels(30.0));
                 
@@ -1,4 +1,5 @@
 use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
 
 use nih_plug::prelude::Enum;
 
@@ -422,4 +423,247 @@ impl BiquadFilter {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// ends of the feedback filter ranges, each filter is bypassed at its end so the loop is left
+// untouched by default
+pub const FEEDBACK_LPF_MAX: f32 = 20000.0;
+pub const FEEDBACK_HPF_MIN: f32 = 20.0;
+// the bilinear designs break down close to nyquist
+const MAX_CUTOFF_RATIO: f32 = 0.45;
+
+// tone control inside a feedback loop, a low-pass followed by a high-pass
+pub struct FeedbackFilters {
+    sample_rate: f32,
+    low_pass: BiquadFilter,
+    high_pass: BiquadFilter,
+    // cutoffs the coefficients were calculated for, None while a filter is bypassed
+    low_pass_cutoff: Option<f32>,
+    high_pass_cutoff: Option<f32>,
+}
+
+impl FeedbackFilters {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 44100.0,
+            low_pass: BiquadFilter::new(),
+            high_pass: BiquadFilter::new(),
+            low_pass_cutoff: None,
+            high_pass_cutoff: None,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.reset();
+        // forces new coefficients for the new sample rate
+        self.low_pass_cutoff = None;
+        self.high_pass_cutoff = None;
+    }
+
+    pub fn reset(&mut self) {
+        self.low_pass.reset_filter();
+        self.high_pass.reset_filter();
+    }
+
+    // coefficients are only recalculated when a cutoff changes, a filter coming back from
+    // bypass starts from silence
+    pub fn set_cutoffs(&mut self, low_pass: f32, high_pass: f32) {
+        let max_cutoff = MAX_CUTOFF_RATIO * self.sample_rate;
+        if low_pass >= FEEDBACK_LPF_MAX {
+            self.low_pass_cutoff = None;
+        } else {
+            let cutoff = low_pass.min(max_cutoff);
+            if self.low_pass_cutoff.is_none() {
+                self.low_pass.reset_filter();
+            }
+            if self.low_pass_cutoff != Some(cutoff) {
+                self.low_pass.second_order_lpf_coefficients(self.sample_rate, cutoff, 0.707);
+                self.low_pass_cutoff = Some(cutoff);
+            }
+        }
+        if high_pass <= FEEDBACK_HPF_MIN {
+            self.high_pass_cutoff = None;
+        } else {
+            let cutoff = high_pass.min(max_cutoff);
+            if self.high_pass_cutoff.is_none() {
+                self.high_pass.reset_filter();
+            }
+            if self.high_pass_cutoff != Some(cutoff) {
+                self.high_pass.second_order_hpf_coefficients(self.sample_rate, cutoff, 0.707);
+                self.high_pass_cutoff = Some(cutoff);
+            }
+        }
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let x = if self.low_pass_cutoff.is_some() { self.low_pass.process_left(x) } else { x };
+        if self.high_pass_cutoff.is_some() { self.high_pass.process_left(x) } else { x }
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let x = if self.low_pass_cutoff.is_some() { self.low_pass.process_right(x) } else { x };
+        if self.high_pass_cutoff.is_some() { self.high_pass.process_right(x) } else { x }
+    }
+}
+
+// sample type of TdfBiquadFilter, f64 keeps low cutoffs at high sample rates accurate
+pub trait FilterPrecision: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn from_f64(x: f64) -> Self;
+    fn from_f32(x: f32) -> Self;
+    fn to_f32(self) -> f32;
+}
+
+impl FilterPrecision for f32 {
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl FilterPrecision for f64 {
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn from_f32(x: f32) -> Self {
+        x as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+// transposed direct form II biquad. only two state values per channel, and with f64
+// state and coefficients the poles of very low cutoffs stay where they should be.
+// coefficients are always calculated in f64
+#[derive(Clone, Copy)]
+pub struct TdfBiquadFilter<T: FilterPrecision> {
+    a0: T,
+    a1: T,
+    a2: T,
+    b0: T,
+    b1: T,
+
+    l_s1: T,
+    l_s2: T,
+    r_s1: T,
+    r_s2: T,
+
+    sample_rate: f32,
+}
+
+impl<T: FilterPrecision> TdfBiquadFilter<T> {
+    pub fn new() -> Self {
+        let zero = T::from_f64(0.0);
+        Self {
+            a0: T::from_f64(1.0),
+            a1: zero,
+            a2: zero,
+            b0: zero,
+            b1: zero,
+            l_s1: zero,
+            l_s2: zero,
+            r_s1: zero,
+            r_s2: zero,
+            sample_rate: 44100.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn reset_filter(&mut self) {
+        let zero = T::from_f64(0.0);
+        self.l_s1 = zero;
+        self.l_s2 = zero;
+        self.r_s1 = zero;
+        self.r_s2 = zero;
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let x = T::from_f32(x);
+        let y = self.a0 * x + self.l_s1;
+        self.l_s1 = self.a1 * x - self.b0 * y + self.l_s2;
+        self.l_s2 = self.a2 * x - self.b1 * y;
+        y.to_f32()
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let x = T::from_f32(x);
+        let y = self.a0 * x + self.r_s1;
+        self.r_s1 = self.a1 * x - self.b0 * y + self.r_s2;
+        self.r_s2 = self.a2 * x - self.b1 * y;
+        y.to_f32()
+    }
+
+    fn set_coefficients(&mut self, a0: f64, a1: f64, a2: f64, b0: f64, b1: f64) {
+        self.a0 = T::from_f64(a0);
+        self.a1 = T::from_f64(a1);
+        self.a2 = T::from_f64(a2);
+        self.b0 = T::from_f64(b0);
+        self.b1 = T::from_f64(b1);
+    }
+
+    // same designs as BiquadFilter
+    pub fn second_order_lpf_coefficients(&mut self, cutoff: f32, q: f32) {
+        let o = 2.0 * std::f64::consts::PI * cutoff as f64 / self.sample_rate as f64;
+        let d = 1.0 / q as f64;
+        let b = 0.5 * ((1.0 - (d / 2.0) * o.sin()) / (1.0 + (d / 2.0) * o.sin()));
+        let y = (0.5 + b) * o.cos();
+        let a0 = (0.5 + b - y) / 2.0;
+        self.set_coefficients(a0, 2.0 * a0, a0, -2.0 * y, 2.0 * b);
+    }
+
+    pub fn second_order_hpf_coefficients(&mut self, cutoff: f32, q: f32) {
+        let o = 2.0 * std::f64::consts::PI * cutoff as f64 / self.sample_rate as f64;
+        let d = 1.0 / q as f64;
+        let b = 0.5 * ((1.0 - (d / 2.0) * o.sin()) / (1.0 + (d / 2.0) * o.sin()));
+        let y = (0.5 + b) * o.cos();
+        let a0 = (0.5 + b + y) / 2.0;
+        self.set_coefficients(a0, -2.0 * a0, a0, -2.0 * y, 2.0 * b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feedback_filters_are_bypassed_at_the_ends() {
+        let mut filters = FeedbackFilters::new();
+        filters.set_sample_rate(48000.0);
+        filters.set_cutoffs(FEEDBACK_LPF_MAX, FEEDBACK_HPF_MIN);
+        for i in 0..100 {
+            let x = (i as f32 * 0.3).sin();
+            assert_eq!(filters.process_left(x), x);
+            assert_eq!(filters.process_right(x), x);
+        }
+    }
+
+    // a cutoff above nyquist is clamped instead of designing an unstable filter
+    #[test]
+    fn feedback_filters_stay_stable_at_low_sample_rates() {
+        for sample_rate in [22050.0, 32000.0, 40000.0] {
+            let mut filters = FeedbackFilters::new();
+            filters.set_sample_rate(sample_rate);
+            filters.set_cutoffs(19000.0, 1000.0);
+            let mut peak: f32 = 0.0;
+            for i in 0..sample_rate as usize {
+                let x = if i == 0 { 1.0 } else { 0.0 };
+                peak = peak.max(filters.process_left(x).abs());
+            }
+            let tail = (0..100).map(|_| filters.process_left(0.0).abs()).fold(0.0, f32::max);
+            assert!(peak.is_finite() && peak < 2.0 && tail < 1e-6, "{} Hz: peak {}, tail {}", sample_rate, peak, tail);
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use std::{f32::consts::PI, ops::Range};
 
-use rand::Rng;
+use nih_plug::prelude::Enum;
 
 pub struct LFO {
     pub rate: f32,
     phase: f32,
+    // phase the LFO goes back to on reset
+    initial_phase: f32,
     pub sample_rate: f32
 }
 
@@ -14,24 +16,25 @@ impl LFO {
             sample_rate,
             rate,
             phase: 0.0,
+            initial_phase: 0.0,
         }
     }
 
-    pub fn new_random_phase(sample_rate: f32, rate: f32) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn new_with_phase(sample_rate: f32, rate: f32, phase: f32) -> Self {
         Self {
             sample_rate,
             rate,
-            phase: rng.gen_range(0.0..(2.0 * PI))
+            phase,
+            initial_phase: phase,
         }
     }
 
-    pub fn new_with_phase(sample_rate: f32, rate: f32, phase: f32) -> Self {
-        Self {
-            sample_rate,
-            rate,
-            phase
-        }
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = phase % (2.0 * PI);
     }
 
     /// returns next value of LFO. Values of <-1, 1>
@@ -39,6 +42,11 @@ impl LFO {
         self.phase.sin()
     }
 
+    /// triangle with the same phase as next_value(). Values of <-1, 1>
+    pub fn next_value_triangle(&mut self) -> f32 {
+        2.0 / PI * self.phase.sin().asin()
+    }
+
     pub fn next_value_range(&mut self, range: Range<f32>) -> f32 {
         let value = self.next_value();
         let scaled = (value + 1.0) / 2.0;
@@ -46,10 +54,174 @@ impl LFO {
         scaled
     }
 
+    pub fn reset(&mut self) {
+        self.phase = self.initial_phase;
+    }
+
     pub fn update_lfo(&mut self) {
         self.phase += 2.0 * std::f32::consts::PI * self.rate / self.sample_rate;
         if self.phase > 2.0 * PI {
             self.phase -= 2.0 * PI;
         }
     }
-}
\ No newline at end of file
+}
+
+// how an LFO value is mapped onto a delay range
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SweepLaw {
+    Linear,
+    Exponential,
+    PitchLinear,
+}
+
+impl Enum for SweepLaw {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Linear",
+            "Exponential",
+            "Pitch-Linear",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "linear",
+            "exponential",
+            "pitch_linear",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            SweepLaw::Linear => 0,
+            SweepLaw::Exponential => 1,
+            SweepLaw::PitchLinear => 2,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => SweepLaw::Linear,
+            1 => SweepLaw::Exponential,
+            2 => SweepLaw::PitchLinear,
+            _ => panic!("Invalid sweep law index."),
+        }
+    }
+}
+
+impl SweepLaw {
+    /// maps an LFO value of <-1, 1> onto a delay between min_delay and max_delay, an LFO
+    /// value of 0 always lands in the middle of the range.
+    /// linear moves the delay evenly, so the notches race through the low end and crawl at
+    /// the top. exponential moves the delay by even ratios on either side of the middle, so
+    /// the notches move by even intervals. pitch-linear also turns the sine into a triangle,
+    /// so the notches move at a constant number of octaves per second
+    pub fn map(self, lfo_value: f32, min_delay: f32, max_delay: f32) -> f32 {
+        let lfo_value = match self {
+            SweepLaw::PitchLinear => 2.0 / PI * lfo_value.clamp(-1.0, 1.0).asin(),
+            _ => lfo_value,
+        };
+        match self {
+            SweepLaw::Linear => min_delay + (max_delay - min_delay) * (lfo_value + 1.0) / 2.0,
+            // a ratio needs a delay above zero at the bottom
+            _ => {
+                let centre = ((min_delay + max_delay) / 2.0).max(1.0);
+                if lfo_value < 0.0 {
+                    centre * (min_delay.clamp(1.0, centre) / centre).powf(-lfo_value)
+                } else {
+                    centre * (max_delay.max(centre) / centre).powf(lfo_value)
+                }
+            }
+        }
+    }
+}
+
+/// smoothed random drift, moves to a new random value in <-1, 1> `rate` times per second
+/// with cosine interpolation in between. seeded, so renders are reproducible
+pub struct Drift {
+    pub rate: f32,
+    pub sample_rate: f32,
+    // position between the current and the next value, 0 to 1
+    position: f32,
+    current: f32,
+    next: f32,
+    seed: u32,
+    noise_state: u32,
+}
+
+impl Drift {
+    pub fn new(sample_rate: f32, rate: f32, seed: u32) -> Self {
+        let mut drift = Self {
+            rate,
+            sample_rate,
+            position: 0.0,
+            current: 0.0,
+            next: 0.0,
+            // xorshift needs a non-zero state
+            seed: seed | 1,
+            noise_state: seed | 1,
+        };
+        drift.reset();
+        drift
+    }
+
+    pub fn reset(&mut self) {
+        self.noise_state = self.seed;
+        self.position = 0.0;
+        self.current = self.next_random();
+        self.next = self.next_random();
+    }
+
+    pub fn next_value(&self) -> f32 {
+        let t = (1.0 - (PI * self.position).cos()) / 2.0;
+        self.current + (self.next - self.current) * t
+    }
+
+    pub fn update_drift(&mut self) {
+        self.position += self.rate / self.sample_rate;
+        if self.position >= 1.0 {
+            self.position -= 1.0;
+            self.current = self.next;
+            self.next = self.next_random();
+        }
+    }
+
+    fn next_random(&mut self) -> f32 {
+        self.noise_state ^= self.noise_state << 13;
+        self.noise_state ^= self.noise_state >> 17;
+        self.noise_state ^= self.noise_state << 5;
+        self.noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAWS: [SweepLaw; 3] = [SweepLaw::Linear, SweepLaw::Exponential, SweepLaw::PitchLinear];
+
+    #[test]
+    fn sweep_is_centred() {
+        for law in LAWS {
+            for (min_delay, max_delay) in [(10.0, 30.0), (0.0, 200.0), (50.0, 60.0), (40.0, 40.0)] {
+                let centre = law.map(0.0, min_delay, max_delay);
+                assert!((centre - (min_delay + max_delay) / 2.0).abs() < 1e-4, "{}: {} to {} is centred on {}", law.to_index(), min_delay, max_delay, centre);
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_reaches_both_ends() {
+        for law in LAWS {
+            let (min_delay, max_delay) = (10.0, 30.0);
+            assert!((law.map(-1.0, min_delay, max_delay) - min_delay).abs() < 1e-4);
+            assert!((law.map(1.0, min_delay, max_delay) - max_delay).abs() < 1e-4);
+            let mut previous = 0.0;
+            for i in 0..=100 {
+                let delay = law.map(i as f32 / 50.0 - 1.0, min_delay, max_delay);
+                assert!(delay >= previous, "{}: the sweep turns back at {}", law.to_index(), i);
+                previous = delay;
+            }
+        }
+    }
+}
@@ -1,4 +1,8 @@
+use bbd::ChorusMode;
 use chorus::Chorus;
+use delay::Interpolation;
+use filter::{FEEDBACK_HPF_MIN, FEEDBACK_LPF_MAX};
+use lfo::SweepLaw;
 use nih_plug::prelude::*;
 use std::{sync::{Arc, mpsc::channel}, collections::VecDeque, env};
 
@@ -10,6 +14,7 @@ mod lfo;
 mod editor;
 mod chorus;
 mod filter;
+mod bbd;
 
 const MAX_BLOCK_SIZE: usize = 32;
 
@@ -19,6 +24,11 @@ struct ScratchBuffer {
     delay: [f32; MAX_BLOCK_SIZE],
     feedback: [f32; MAX_BLOCK_SIZE],
     mix: [f32; MAX_BLOCK_SIZE],
+    width: [f32; MAX_BLOCK_SIZE],
+    feedback_lpf: [f32; MAX_BLOCK_SIZE],
+    feedback_hpf: [f32; MAX_BLOCK_SIZE],
+    delay_spread: [f32; MAX_BLOCK_SIZE],
+    rate_spread: [f32; MAX_BLOCK_SIZE],
 }
 
 impl Default for ScratchBuffer {
@@ -29,6 +39,11 @@ impl Default for ScratchBuffer {
             delay: [0.0; MAX_BLOCK_SIZE],
             feedback: [0.0; MAX_BLOCK_SIZE],
             mix: [0.0; MAX_BLOCK_SIZE],
+            width: [0.0; MAX_BLOCK_SIZE],
+            feedback_lpf: [0.0; MAX_BLOCK_SIZE],
+            feedback_hpf: [0.0; MAX_BLOCK_SIZE],
+            delay_spread: [0.0; MAX_BLOCK_SIZE],
+            rate_spread: [0.0; MAX_BLOCK_SIZE],
         }
     }
 }
@@ -37,7 +52,7 @@ pub struct ChorusPlugin {
     params: Arc<ChorusParams>,
     sample_rate: f32,
     chorus: chorus::Chorus,
-    output_hpf: filter::BiquadFilter,
+    output_hpf: filter::TdfBiquadFilter<f64>,
     scr_buf: ScratchBuffer,
 }
 
@@ -59,6 +74,24 @@ struct ChorusParams {
     pub mix: FloatParam,
     #[id = "mono"]
     pub mono: BoolParam,
+    #[id = "voices"]
+    pub voices: IntParam,
+    #[id = "width"]
+    pub width: FloatParam,
+    #[id = "mode"]
+    pub mode: EnumParam<ChorusMode>,
+    #[id = "quality"]
+    pub quality: EnumParam<Interpolation>,
+    #[id = "sweep_law"]
+    pub sweep_law: EnumParam<SweepLaw>,
+    #[id = "delay_spread"]
+    pub delay_spread: FloatParam,
+    #[id = "rate_spread"]
+    pub rate_spread: FloatParam,
+    #[id = "feedback_lpf"]
+    pub feedback_lpf: FloatParam,
+    #[id = "feedback_hpf"]
+    pub feedback_hpf: FloatParam,
 
     #[id = "credits"]
     pub credits: BoolParam,
@@ -70,7 +103,7 @@ impl Default for ChorusPlugin {
             params: Arc::new(ChorusParams::default()),
             sample_rate: 44100.0,
             chorus: Chorus::new(44100.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0),
-            output_hpf: filter::BiquadFilter::new(),
+            output_hpf: filter::TdfBiquadFilter::new(),
             scr_buf: ScratchBuffer::default(),
         }
     }
@@ -115,6 +148,49 @@ impl Default for ChorusParams {
             // MONO
             mono: BoolParam::new("Mono", false),
 
+            // VOICES
+            voices: IntParam::new("Voices", 3, IntRange::Linear { min: 1, max: chorus::MAX_VOICES as i32 }),
+
+            // WIDTH
+            width: FloatParam::new("Width", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(15.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // MODE
+            mode: EnumParam::new("Mode", ChorusMode::Digital),
+
+            // QUALITY
+            quality: EnumParam::new("Quality", Interpolation::Linear),
+
+            // SWEEP LAW
+            sweep_law: EnumParam::new("Sweep Law", SweepLaw::Linear),
+
+            // SPREAD
+            delay_spread: FloatParam::new("Delay Spread", 0.0, FloatRange::Linear { min: 0.0, max: 20.0 })
+            .with_smoother(SmoothingStyle::Linear(15.0))
+            .with_unit("ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            rate_spread: FloatParam::new("Rate Spread", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_smoother(SmoothingStyle::Linear(15.0))
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(1))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            // FEEDBACK FILTERS
+            // off at the top of the range
+            feedback_lpf: FloatParam::new("Feedback Low-Pass", FEEDBACK_LPF_MAX, FloatRange::Skewed { min: 200.0, max: FEEDBACK_LPF_MAX, factor: 0.3 })
+            .with_smoother(SmoothingStyle::Logarithmic(15.0))
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            // off at the bottom of the range
+            feedback_hpf: FloatParam::new("Feedback High-Pass", FEEDBACK_HPF_MIN, FloatRange::Skewed { min: FEEDBACK_HPF_MIN, max: 2000.0, factor: 0.3 })
+            .with_smoother(SmoothingStyle::Logarithmic(15.0))
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
 
             // CREDITS
             credits: BoolParam::new("Credits", false),
@@ -163,11 +239,11 @@ impl Plugin for ChorusPlugin {
         _buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
-        self.sample_rate = 2.0 * _buffer_config.sample_rate as f32;
+        self.sample_rate = _buffer_config.sample_rate as f32;
 
         self.chorus.resize_buffers(self.sample_rate);
         self.output_hpf.set_sample_rate(_buffer_config.sample_rate as f32);
-        self.output_hpf.coefficients(filter::FilterType::HighPass2, 25.0, 0.707, 1.0);
+        self.output_hpf.second_order_hpf_coefficients(25.0, 0.707);
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
@@ -177,6 +253,8 @@ impl Plugin for ChorusPlugin {
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.chorus.reset();
+        self.output_hpf.reset_filter();
     }
 
     fn process(
@@ -188,9 +266,9 @@ impl Plugin for ChorusPlugin {
 
         // In current configuration this function iterates as follows:
         // 1. outer loop iterates block-size times
-        // 2. inner loop iterates channel-size times. 
+        // 2. inner loop iterates sample-size times, processing both channels of a frame.
 
-        for (_, block) in buffer.iter_blocks(MAX_BLOCK_SIZE) {
+        for (_, mut block) in buffer.iter_blocks(MAX_BLOCK_SIZE) {
             let block_len = block.samples();
 
             let rate = &mut self.scr_buf.rate;
@@ -208,30 +286,57 @@ impl Plugin for ChorusPlugin {
             let mix = &mut self.scr_buf.mix;
             self.params.mix.smoothed.next_block(mix, block_len);
 
-            let mono = self.params.mono.value();
+            let width = &mut self.scr_buf.width;
+            self.params.width.smoothed.next_block(width, block_len);
+
+            let feedback_lpf = &mut self.scr_buf.feedback_lpf;
+            self.params.feedback_lpf.smoothed.next_block(feedback_lpf, block_len);
+
+            let feedback_hpf = &mut self.scr_buf.feedback_hpf;
+            self.params.feedback_hpf.smoothed.next_block(feedback_hpf, block_len);
 
-            for (channel_idx, block_channel) in block.into_iter().enumerate() {
-
-                for (sample_idx, sample) in block_channel.into_iter().enumerate() {
-
-                    self.chorus.set_params(
-                        self.sample_rate, 
-                        unsafe { *delay.get_unchecked(sample_idx)}, 
-                        unsafe { *feedback.get_unchecked(sample_idx)},
-                        unsafe { *depth.get_unchecked(sample_idx)},
-                        unsafe { *rate.get_unchecked(sample_idx)},
-                        unsafe { *mix.get_unchecked(sample_idx)},
-                        mono,);
-                    
-                    if channel_idx == 0 {
-                        *sample = self.chorus.process_left(*sample);
-                        *sample = self.output_hpf.process_left(*sample);
-                    } else {
-                        *sample = self.chorus.process_right(*sample);
-                        *sample = self.output_hpf.process_right(*sample);
-                    }
-                    self.chorus.update_modulators();
+            let delay_spread = &mut self.scr_buf.delay_spread;
+            self.params.delay_spread.smoothed.next_block(delay_spread, block_len);
+
+            let rate_spread = &mut self.scr_buf.rate_spread;
+            self.params.rate_spread.smoothed.next_block(rate_spread, block_len);
+
+            let mono = self.params.mono.value();
+            let voices = self.params.voices.value() as usize;
+            let mode = self.params.mode.value();
+            let quality = self.params.quality.value();
+            self.chorus.set_sweep_law(self.params.sweep_law.value());
+
+            for (sample_idx, mut channel_samples) in block.iter_samples().enumerate() {
+                self.chorus.set_spread(
+                    unsafe { *delay_spread.get_unchecked(sample_idx)},
+                    unsafe { *rate_spread.get_unchecked(sample_idx)},);
+
+                self.chorus.set_params(
+                    self.sample_rate, 
+                    unsafe { *delay.get_unchecked(sample_idx)}, 
+                    unsafe { *feedback.get_unchecked(sample_idx)},
+                    unsafe { *depth.get_unchecked(sample_idx)},
+                    unsafe { *rate.get_unchecked(sample_idx)},
+                    unsafe { *mix.get_unchecked(sample_idx)},
+                    mono,
+                    voices,
+                    unsafe { *width.get_unchecked(sample_idx)},
+                    mode,
+                    quality,);
+                self.chorus.set_feedback_filters(
+                    unsafe { *feedback_lpf.get_unchecked(sample_idx)},
+                    unsafe { *feedback_hpf.get_unchecked(sample_idx)},);
+
+                // the voices are panned across both channels, so a whole frame is processed at once
+                unsafe {
+                    let l = *channel_samples.get_unchecked_mut(0);
+                    let r = *channel_samples.get_unchecked_mut(1);
+                    let (l, r) = self.chorus.process(l, r);
+                    *channel_samples.get_unchecked_mut(0) = self.output_hpf.process_left(l);
+                    *channel_samples.get_unchecked_mut(1) = self.output_hpf.process_right(r);
                 }
+                self.chorus.update_modulators();
             }
         }
         // for (i, channel_samples) in buffer.iter_samples().enumerate() {
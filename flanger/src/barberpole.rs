@@ -0,0 +1,210 @@
+use std::f32::consts::PI;
+
+use nih_plug::prelude::Enum;
+
+use crate::{delay::{Delay, Interpolation}, lfo::SweepLaw};
+
+// number of crossfaded taps. the taps sweep a third of a cycle apart and their sin^2
+// windows always add up to 1.5, so the level stays constant while each tap fades out at
+// the end of its sweep and back in at the start
+const TAPS: usize = 3;
+const TAP_GAIN: f32 = 2.0 / TAPS as f32;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl Enum for Direction {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Up",
+            "Down",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "up",
+            "down",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            Direction::Up => 0,
+            Direction::Down => 1,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => Direction::Up,
+            1 => Direction::Down,
+            _ => panic!("Invalid direction index."),
+        }
+    }
+}
+
+// length of one LFO cycle when the rate is synced to the host tempo, assumes 4/4
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NoteLength {
+    FourBars,
+    TwoBars,
+    OneBar,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+}
+
+impl NoteLength {
+    pub fn beats(self) -> f32 {
+        match self {
+            NoteLength::FourBars => 16.0,
+            NoteLength::TwoBars => 8.0,
+            NoteLength::OneBar => 4.0,
+            NoteLength::Half => 2.0,
+            NoteLength::Quarter => 1.0,
+            NoteLength::Eighth => 0.5,
+            NoteLength::Sixteenth => 0.25,
+        }
+    }
+
+    // LFO rate in Hz for the given tempo
+    pub fn rate(self, tempo: f32) -> f32 {
+        tempo / 60.0 / self.beats()
+    }
+}
+
+impl Enum for NoteLength {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "4 Bars",
+            "2 Bars",
+            "1 Bar",
+            "1/2",
+            "1/4",
+            "1/8",
+            "1/16",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "4_bars",
+            "2_bars",
+            "1_bar",
+            "1_2",
+            "1_4",
+            "1_8",
+            "1_16",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            NoteLength::FourBars => 0,
+            NoteLength::TwoBars => 1,
+            NoteLength::OneBar => 2,
+            NoteLength::Half => 3,
+            NoteLength::Quarter => 4,
+            NoteLength::Eighth => 5,
+            NoteLength::Sixteenth => 6,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => NoteLength::FourBars,
+            1 => NoteLength::TwoBars,
+            2 => NoteLength::OneBar,
+            3 => NoteLength::Half,
+            4 => NoteLength::Quarter,
+            5 => NoteLength::Eighth,
+            6 => NoteLength::Sixteenth,
+            _ => panic!("Invalid note length index."),
+        }
+    }
+}
+
+// endlessly rising or falling flanger. every tap is swept by a sawtooth from one end of the
+// delay range to the other and crossfaded so the jump back is never heard, like a Shepard tone
+pub struct BarberPole {
+    left_taps: Vec<Delay>,
+    right_taps: Vec<Delay>,
+    direction: Direction,
+    sweep_law: SweepLaw,
+}
+
+impl BarberPole {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut left_taps: Vec<Delay> = Vec::with_capacity(TAPS);
+        let mut right_taps: Vec<Delay> = Vec::with_capacity(TAPS);
+        for _ in 0..TAPS {
+            left_taps.push(Delay::new(sample_rate as usize, 0.0));
+            right_taps.push(Delay::new(sample_rate as usize, 0.0));
+        }
+
+        Self {
+            left_taps,
+            right_taps,
+            direction: Direction::Up,
+            sweep_law: SweepLaw::Linear,
+        }
+    }
+
+    pub fn resize_buffers(&mut self, sample_rate: f32) {
+        for tap in self.left_taps.iter_mut().chain(self.right_taps.iter_mut()) {
+            tap.resize_buffers(sample_rate as usize);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for tap in self.left_taps.iter_mut().chain(self.right_taps.iter_mut()) {
+            tap.reset();
+        }
+    }
+
+    pub fn set_params(&mut self, direction: Direction, interpolation: Interpolation) {
+        self.direction = direction;
+        for tap in self.left_taps.iter_mut().chain(self.right_taps.iter_mut()) {
+            tap.set_interpolation(interpolation);
+        }
+    }
+
+    pub fn set_sweep_law(&mut self, sweep_law: SweepLaw) {
+        // the sawtooth already moves at a constant speed, so pitch-linear is the same as
+        // exponential here
+        self.sweep_law = match sweep_law {
+            SweepLaw::PitchLinear => SweepLaw::Exponential,
+            law => law,
+        };
+    }
+
+    // ramp is the position of the sweep, 0 to 1. the delays go from min_delay to max_delay
+    pub fn process_left(&mut self, x: f32, ramp: f32, min_delay: f32, max_delay: f32) -> f32 {
+        process_taps(&mut self.left_taps, self.direction, self.sweep_law, x, ramp, min_delay, max_delay)
+    }
+
+    pub fn process_right(&mut self, x: f32, ramp: f32, min_delay: f32, max_delay: f32) -> f32 {
+        process_taps(&mut self.right_taps, self.direction, self.sweep_law, x, ramp, min_delay, max_delay)
+    }
+}
+
+fn process_taps(taps: &mut [Delay], direction: Direction, sweep_law: SweepLaw, x: f32, ramp: f32, min_delay: f32, max_delay: f32) -> f32 {
+    let mut y = 0.0;
+    for (i, tap) in taps.iter_mut().enumerate() {
+        let position = (ramp + i as f32 / TAPS as f32).fract();
+        // a shrinking delay moves the notches up
+        let sweep = match direction {
+            Direction::Up => 1.0 - position,
+            Direction::Down => position,
+        };
+        let delay = sweep_law.map(2.0 * sweep - 1.0, min_delay, max_delay);
+        let gain = (PI * position).sin().powi(2);
+        y += gain * tap.process_sample(x, delay);
+    }
+    TAP_GAIN * y
+}
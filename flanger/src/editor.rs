@@ -61,7 +61,7 @@ struct Data {
 impl Model for Data {}
 
 pub(crate) fn default_state() -> Arc<ViziaState> {
-    ViziaState::new(|| (350, 350))
+    ViziaState::new(|| (350, 710))
 }
 
 pub(crate) fn create(
@@ -111,11 +111,59 @@ pub(crate) fn create(
                         ParamKnob::new(cx, Data::phaser_data, |params| &params.dry, false)
                         .height(Pixels(30.0));
 
-                        ParamButton::new(cx, Data::phaser_data, |params| &params.stereo)
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.stereo_phase, false)
+                        .height(Pixels(30.0));
+                        
+                    }).col_between(Pixels(15.0));
+
+                    HStack::new(cx, |cx| {
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.quality, false)
+                        .height(Pixels(30.0));
+
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.feedback_lpf, false)
+                        .height(Pixels(30.0));
+
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.feedback_hpf, false)
+                        .height(Pixels(30.0));
+
+                    }).col_between(Pixels(15.0));
+
+                    HStack::new(cx, |cx| {
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.manual, false)
+                        .height(Pixels(30.0));
+
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.sweep_law, false)
+                        .height(Pixels(30.0));
+
+                        ParamButton::new(cx, Data::phaser_data, |params| &params.freeze)
                         .height(Pixels(30.0))
                         .space(Stretch(1.0))
                         .bottom(Percentage(51.0));
-                        
+
+                        ParamButton::new(cx, Data::phaser_data, |params| &params.through_zero)
+                        .height(Pixels(30.0))
+                        .space(Stretch(1.0))
+                        .bottom(Percentage(51.0));
+
+                    }).col_between(Pixels(15.0));
+
+                    HStack::new(cx, |cx| {
+                        ParamButton::new(cx, Data::phaser_data, |params| &params.barber_pole)
+                        .height(Pixels(30.0))
+                        .space(Stretch(1.0))
+                        .bottom(Percentage(51.0));
+
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.direction, false)
+                        .height(Pixels(30.0));
+
+                        ParamButton::new(cx, Data::phaser_data, |params| &params.tempo_sync)
+                        .height(Pixels(30.0))
+                        .space(Stretch(1.0))
+                        .bottom(Percentage(51.0));
+
+                        ParamKnob::new(cx, Data::phaser_data, |params| &params.sync_length, false)
+                        .height(Pixels(30.0));
+
                     }).col_between(Pixels(15.0));
 
                 }).col_between(Pixels(30.0));
@@ -422,4 +422,120 @@ impl BiquadFilter {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// ends of the feedback filter ranges, each filter is bypassed at its end so the loop is left
+// untouched by default
+pub const FEEDBACK_LPF_MAX: f32 = 20000.0;
+pub const FEEDBACK_HPF_MIN: f32 = 20.0;
+// the bilinear designs break down close to nyquist
+const MAX_CUTOFF_RATIO: f32 = 0.45;
+
+// tone control inside a feedback loop, a low-pass followed by a high-pass
+pub struct FeedbackFilters {
+    sample_rate: f32,
+    low_pass: BiquadFilter,
+    high_pass: BiquadFilter,
+    // cutoffs the coefficients were calculated for, None while a filter is bypassed
+    low_pass_cutoff: Option<f32>,
+    high_pass_cutoff: Option<f32>,
+}
+
+impl FeedbackFilters {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 44100.0,
+            low_pass: BiquadFilter::new(),
+            high_pass: BiquadFilter::new(),
+            low_pass_cutoff: None,
+            high_pass_cutoff: None,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.reset();
+        // forces new coefficients for the new sample rate
+        self.low_pass_cutoff = None;
+        self.high_pass_cutoff = None;
+    }
+
+    pub fn reset(&mut self) {
+        self.low_pass.reset_filter();
+        self.high_pass.reset_filter();
+    }
+
+    // coefficients are only recalculated when a cutoff changes, a filter coming back from
+    // bypass starts from silence
+    pub fn set_cutoffs(&mut self, low_pass: f32, high_pass: f32) {
+        let max_cutoff = MAX_CUTOFF_RATIO * self.sample_rate;
+        if low_pass >= FEEDBACK_LPF_MAX {
+            self.low_pass_cutoff = None;
+        } else {
+            let cutoff = low_pass.min(max_cutoff);
+            if self.low_pass_cutoff.is_none() {
+                self.low_pass.reset_filter();
+            }
+            if self.low_pass_cutoff != Some(cutoff) {
+                self.low_pass.second_order_lpf_coefficients(self.sample_rate, cutoff, 0.707);
+                self.low_pass_cutoff = Some(cutoff);
+            }
+        }
+        if high_pass <= FEEDBACK_HPF_MIN {
+            self.high_pass_cutoff = None;
+        } else {
+            let cutoff = high_pass.min(max_cutoff);
+            if self.high_pass_cutoff.is_none() {
+                self.high_pass.reset_filter();
+            }
+            if self.high_pass_cutoff != Some(cutoff) {
+                self.high_pass.second_order_hpf_coefficients(self.sample_rate, cutoff, 0.707);
+                self.high_pass_cutoff = Some(cutoff);
+            }
+        }
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let x = if self.low_pass_cutoff.is_some() { self.low_pass.process_left(x) } else { x };
+        if self.high_pass_cutoff.is_some() { self.high_pass.process_left(x) } else { x }
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let x = if self.low_pass_cutoff.is_some() { self.low_pass.process_right(x) } else { x };
+        if self.high_pass_cutoff.is_some() { self.high_pass.process_right(x) } else { x }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feedback_filters_are_bypassed_at_the_ends() {
+        let mut filters = FeedbackFilters::new();
+        filters.set_sample_rate(48000.0);
+        filters.set_cutoffs(FEEDBACK_LPF_MAX, FEEDBACK_HPF_MIN);
+        for i in 0..100 {
+            let x = (i as f32 * 0.3).sin();
+            assert_eq!(filters.process_left(x), x);
+            assert_eq!(filters.process_right(x), x);
+        }
+    }
+
+    // a cutoff above nyquist is clamped instead of designing an unstable filter
+    #[test]
+    fn feedback_filters_stay_stable_at_low_sample_rates() {
+        for sample_rate in [22050.0, 32000.0, 40000.0] {
+            let mut filters = FeedbackFilters::new();
+            filters.set_sample_rate(sample_rate);
+            filters.set_cutoffs(19000.0, 1000.0);
+            let mut peak: f32 = 0.0;
+            for i in 0..sample_rate as usize {
+                let x = if i == 0 { 1.0 } else { 0.0 };
+                peak = peak.max(filters.process_left(x).abs());
+            }
+            let tail = (0..100).map(|_| filters.process_left(0.0).abs()).fold(0.0, f32::max);
+            assert!(peak.is_finite() && peak < 2.0 && tail < 1e-6, "{} Hz: peak {}, tail {}", sample_rate, peak, tail);
+        }
+    }
+}
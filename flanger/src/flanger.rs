@@ -1,26 +1,40 @@
 use std::{collections::VecDeque, char::MAX, f32::consts::PI};
 
-use crate::{lfo::{self, LFO}, delay::{Delay, self}, filter::BiquadFilter};
+use crate::{lfo::{self, LFO, SweepLaw}, delay::{Delay, Interpolation, self}, filter::{BiquadFilter, FeedbackFilters}, barberpole::{BarberPole, Direction}};
 
 const MAX_FLANGER_DELAY: f32 = 0.015; // 15ms
+// in through-zero mode the dry path is delayed by this much and the wet delay sweeps around it
+const THROUGH_ZERO_DELAY: f32 = MAX_FLANGER_DELAY / 2.0;
 
 pub struct Flanger {
     sample_rate: f32,
     depth: f32,
-    calculated_depth: f32,
+    // centre of the sweep in samples, depth sweeps around it
+    manual: f32,
+    // holds the sweep at the manual position, a static comb filter
+    freeze: bool,
+    sweep_law: SweepLaw,
     rate: f32,
     feedback: f32,
-    left_lfo: lfo::LFO,
-    right_lfo: lfo::LFO,
+    // the right channel reads the same LFO shifted by stereo_phase, so the channels can't drift apart
+    lfo: lfo::LFO,
     left_delay: Delay,
     right_delay: Delay,
     left_feedback_buffer: f32,
     right_feedback_buffer: f32,
-    use_stereo_lfo: bool,
+    // offset of the right channel's modulation in radians
+    stereo_phase: f32,
+    through_zero: bool,
+    left_dry_delay: Delay,
+    right_dry_delay: Delay,
+    barber_pole: BarberPole,
+    use_barber_pole: bool,
     wet: f32,
     dry: f32,
 
     out_hpf: BiquadFilter,
+    // tone control inside the feedback loop
+    feedback_filters: FeedbackFilters,
 }
 
 impl Flanger {
@@ -29,64 +43,148 @@ impl Flanger {
         let mut right_delay = Delay::new(sample_rate as usize, 0.0);
         left_delay.resize_buffers(sample_rate as usize);
         right_delay.resize_buffers(sample_rate as usize);
+        let left_dry_delay = Delay::new(sample_rate as usize, 0.0);
+        let right_dry_delay = Delay::new(sample_rate as usize, 0.0);
 
         Self {
             sample_rate,
             depth: 0.0,
-            calculated_depth: 0.0,
+            manual: 0.0,
+            freeze: false,
+            sweep_law: SweepLaw::Linear,
             rate: 0.0,
             feedback: 0.0,
-            left_lfo: LFO::new_with_phase(sample_rate, 0.2, 0.0),
-            right_lfo: LFO::new_with_phase(sample_rate, 0.2, PI / 2.0),
+            lfo: LFO::new(sample_rate, 0.2),
             left_delay,
             right_delay,
             left_feedback_buffer: 0.0,
             right_feedback_buffer: 0.0,
-            use_stereo_lfo: false,
+            stereo_phase: 0.0,
+            through_zero: false,
+            left_dry_delay,
+            right_dry_delay,
+            barber_pole: BarberPole::new(sample_rate),
+            use_barber_pole: false,
             wet: 0.0,
             dry: 0.0,
             out_hpf: BiquadFilter::new(),
+            feedback_filters: FeedbackFilters::new(),
         }
     }
 
     pub fn resize_buffers(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
         self.left_delay.resize_buffers(sample_rate as usize);
         self.right_delay.resize_buffers(sample_rate as usize);
+        self.left_dry_delay.resize_buffers(sample_rate as usize);
+        self.right_dry_delay.resize_buffers(sample_rate as usize);
+        self.barber_pole.resize_buffers(sample_rate);
         self.left_feedback_buffer = 0.0;
         self.right_feedback_buffer = 0.0;
 
-        self.left_lfo.set_sample_rate(sample_rate as f32);
-        self.right_lfo.set_sample_rate(sample_rate as f32);
+        self.lfo.set_sample_rate(sample_rate as f32);
 
         self.out_hpf.set_sample_rate(sample_rate);
         self.out_hpf.second_order_hpf_coefficients(sample_rate, 30.0, 0.750);
+
+        self.feedback_filters.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_feedback_filters(&mut self, low_pass: f32, high_pass: f32) {
+        self.feedback_filters.set_cutoffs(low_pass, high_pass);
+    }
+
+    // clears the delay lines, feedback and filter state and restarts the LFOs
+    pub fn reset(&mut self) {
+        self.left_delay.reset();
+        self.right_delay.reset();
+        self.left_dry_delay.reset();
+        self.right_dry_delay.reset();
+        self.barber_pole.reset();
+        self.left_feedback_buffer = 0.0;
+        self.right_feedback_buffer = 0.0;
+        self.lfo.reset();
+
+        self.out_hpf.reset_filter();
+        self.feedback_filters.reset();
+    }
+
+    // delay of the dry path in through-zero mode, in whole samples so it can be reported
+    // to the host as latency
+    pub fn through_zero_latency(&self) -> u32 {
+        (THROUGH_ZERO_DELAY * self.sample_rate).round() as u32
     }
 
-    pub fn set_params(&mut self, depth: f32, rate: f32, feedback: f32, wet: f32, dry: f32, stereo: bool) {
+    pub fn set_barber_pole(&mut self, enabled: bool, direction: Direction, interpolation: Interpolation) {
+        // the taps only run while the mode is on, so they'd start from whatever they held when it was turned off
+        if enabled && !self.use_barber_pole {
+            self.barber_pole.reset();
+        }
+        self.use_barber_pole = enabled;
+        self.barber_pole.set_params(direction, interpolation);
+    }
+
+    pub fn set_sweep_law(&mut self, sweep_law: SweepLaw) {
+        self.sweep_law = sweep_law;
+        self.barber_pole.set_sweep_law(sweep_law);
+    }
+
+    pub fn set_params(&mut self, depth: f32, rate: f32, feedback: f32, wet: f32, dry: f32, stereo_phase: f32, interpolation: Interpolation, through_zero: bool, manual: f32, freeze: bool) {
         self.rate = rate;
-        self.left_lfo.rate = rate;
-        self.right_lfo.rate = rate;
+        self.lfo.rate = rate;
 
         self.feedback = feedback;
 
         self.depth = depth;
-        self.calculated_depth = (depth * MAX_FLANGER_DELAY) * self.sample_rate;
+        self.manual = (manual / 1000.0).min(MAX_FLANGER_DELAY) * self.sample_rate;
+        self.freeze = freeze;
+
+        self.left_delay.set_interpolation(interpolation);
+        self.right_delay.set_interpolation(interpolation);
 
-        self.use_stereo_lfo = stereo;   
+        self.stereo_phase = stereo_phase.to_radians();
+        // same for the dry delay lines, which only run in through-zero mode
+        if through_zero && !self.through_zero {
+            self.left_dry_delay.reset();
+            self.right_dry_delay.reset();
+        }
+        self.through_zero = through_zero;
         self.wet = wet;
         self.dry = dry;     
     }
 
+    // moves the LFO so the next sample reads it at phase, in cycles. process_left()
+    // advances the LFO before reading it, so it's set one step back
+    pub fn sync_lfo(&mut self, phase: f32) {
+        let step = self.lfo.rate / self.sample_rate;
+        self.lfo.set_phase(2.0 * PI * (phase - step));
+    }
+
     pub fn process_left(&mut self, x: f32) -> f32 {
-        self.right_lfo.update_lfo();
-        self.left_lfo.update_lfo();
+        self.lfo.update_lfo();
 
         let xx = x + self.left_feedback_buffer * self.feedback;
+        let lfo_value = self.lfo.next_value();
         let delayed_signal = self.left_delay.process_sample(
             xx, 
-            self.left_lfo.next_value_range(0.05..1.0) * self.calculated_depth );
+            self.sweep_delay(lfo_value) );
+        let delayed_signal = if self.use_barber_pole {
+            self.barber_pole.process_left(
+                xx,
+                self.lfo.next_value_ramp(0.0),
+                self.sweep_delay(-1.0),
+                self.sweep_delay(1.0))
+        } else {
+            delayed_signal
+        };
 
-        self.left_feedback_buffer = delayed_signal;
+        let x = if self.through_zero {
+            self.left_dry_delay.process_sample(x, self.through_zero_latency() as f32)
+        } else {
+            x
+        };
+
+        self.left_feedback_buffer = self.feedback_filters.process_left(delayed_signal);
 
         if self.wet + self.dry > 1.0 {
             return self.out_hpf.process_left((self.dry * x + self.wet * delayed_signal) / (self.wet + self.dry))
@@ -96,19 +194,30 @@ impl Flanger {
     }
 
     pub fn process_right(&mut self, x: f32) -> f32 {
-        let lfo_value = if self.use_stereo_lfo {
-            self.right_lfo.next_value_range(0.05..1.0)
-        } else {
-            self.left_lfo.next_value_range(0.05..1.0)
-        };
+        let lfo_value = self.lfo.next_value_with_offset(self.stereo_phase);
 
         let xx = x + self.right_feedback_buffer * self.feedback;
 
         let delayed_signal = self.right_delay.process_sample(
             xx, 
-            lfo_value * self.calculated_depth );
+            self.sweep_delay(lfo_value) );
+        let delayed_signal = if self.use_barber_pole {
+            self.barber_pole.process_right(
+                xx,
+                self.lfo.next_value_ramp(self.stereo_phase),
+                self.sweep_delay(-1.0),
+                self.sweep_delay(1.0))
+        } else {
+            delayed_signal
+        };
 
-        self.right_feedback_buffer = delayed_signal;
+        let x = if self.through_zero {
+            self.right_dry_delay.process_sample(x, self.through_zero_latency() as f32)
+        } else {
+            x
+        };
+
+        self.right_feedback_buffer = self.feedback_filters.process_right(delayed_signal);
 
         if self.wet + self.dry > 1.0 {
             return self.out_hpf.process_right((self.dry * x + self.wet * delayed_signal) / (self.wet + self.dry))
@@ -116,4 +225,17 @@ impl Flanger {
             return self.out_hpf.process_right(self.dry * x + self.wet * delayed_signal)
         }
     }
-}
\ No newline at end of file
+
+    // maps an LFO value of <-1, 1> to the wet delay in samples. the normal sweep goes
+    // around the manual delay and stays above zero, through-zero sweeps around the delayed
+    // dry path and can cross it
+    fn sweep_delay(&self, lfo_value: f32) -> f32 {
+        let lfo_value = if self.freeze { 0.0 } else { lfo_value };
+        let centre = if self.through_zero {
+            self.through_zero_latency() as f32
+        } else {
+            self.manual
+        };
+        self.sweep_law.map(lfo_value, centre * (1.0 - self.depth), centre * (1.0 + self.depth))
+    }
+}
@@ -1,11 +1,13 @@
 use std::{f32::consts::PI, ops::Range};
 
-use rand::Rng;
+use nih_plug::prelude::Enum;
 
 #[derive(Clone)]
 pub struct LFO {
     pub rate: f32,
     phase: f32,
+    // phase the LFO goes back to on reset
+    initial_phase: f32,
     pub sample_rate: f32
 }
 
@@ -15,15 +17,7 @@ impl LFO {
             sample_rate,
             rate,
             phase: 0.0,
-        }
-    }
-
-    pub fn new_random_phase(sample_rate: f32, rate: f32) -> Self {
-        let mut rng = rand::thread_rng();
-        Self {
-            sample_rate,
-            rate,
-            phase: rng.gen_range(0.0..(2.0 * PI))
+            initial_phase: 0.0,
         }
     }
 
@@ -35,15 +29,31 @@ impl LFO {
         Self {
             sample_rate,
             rate,
-            phase
+            phase,
+            initial_phase: phase,
         }
     }
 
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = phase.rem_euclid(2.0 * PI);
+    }
+
     /// returns next value of LFO. Values of <-1, 1>
     pub fn next_value(&mut self) -> f32 {
         self.phase.sin()
     }
 
+    /// value of the LFO shifted by an offset in radians, for deriving another channel's
+    /// modulation from this one. Values of <-1, 1>
+    pub fn next_value_with_offset(&self, offset: f32) -> f32 {
+        (self.phase + offset).sin()
+    }
+
+    /// rising sawtooth with the same phase as next_value_with_offset(). Values of <0, 1)
+    pub fn next_value_ramp(&self, offset: f32) -> f32 {
+        ((self.phase + offset) / (2.0 * PI)).rem_euclid(1.0)
+    }
+
     pub fn next_value_range(&mut self, range: Range<f32>) -> f32 {
         let value = self.next_value();
         let scaled = (value + 1.0) / 2.0;
@@ -51,10 +61,116 @@ impl LFO {
         scaled
     }
 
+    pub fn reset(&mut self) {
+        self.phase = self.initial_phase;
+    }
+
     pub fn update_lfo(&mut self) {
         self.phase += 2.0 * std::f32::consts::PI * self.rate / self.sample_rate;
         if self.phase > 2.0 * PI {
             self.phase -= 2.0 * PI;
         }
     }
-}
\ No newline at end of file
+}
+
+// how an LFO value is mapped onto a delay range
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SweepLaw {
+    Linear,
+    Exponential,
+    PitchLinear,
+}
+
+impl Enum for SweepLaw {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Linear",
+            "Exponential",
+            "Pitch-Linear",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "linear",
+            "exponential",
+            "pitch_linear",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            SweepLaw::Linear => 0,
+            SweepLaw::Exponential => 1,
+            SweepLaw::PitchLinear => 2,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => SweepLaw::Linear,
+            1 => SweepLaw::Exponential,
+            2 => SweepLaw::PitchLinear,
+            _ => panic!("Invalid sweep law index."),
+        }
+    }
+}
+
+impl SweepLaw {
+    /// maps an LFO value of <-1, 1> onto a delay between min_delay and max_delay, an LFO
+    /// value of 0 always lands in the middle of the range.
+    /// linear moves the delay evenly, so the notches race through the low end and crawl at
+    /// the top. exponential moves the delay by even ratios on either side of the middle, so
+    /// the notches move by even intervals. pitch-linear also turns the sine into a triangle,
+    /// so the notches move at a constant number of octaves per second
+    pub fn map(self, lfo_value: f32, min_delay: f32, max_delay: f32) -> f32 {
+        let lfo_value = match self {
+            SweepLaw::PitchLinear => 2.0 / PI * lfo_value.clamp(-1.0, 1.0).asin(),
+            _ => lfo_value,
+        };
+        match self {
+            SweepLaw::Linear => min_delay + (max_delay - min_delay) * (lfo_value + 1.0) / 2.0,
+            // a ratio needs a delay above zero at the bottom
+            _ => {
+                let centre = ((min_delay + max_delay) / 2.0).max(1.0);
+                if lfo_value < 0.0 {
+                    centre * (min_delay.clamp(1.0, centre) / centre).powf(-lfo_value)
+                } else {
+                    centre * (max_delay.max(centre) / centre).powf(lfo_value)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAWS: [SweepLaw; 3] = [SweepLaw::Linear, SweepLaw::Exponential, SweepLaw::PitchLinear];
+
+    #[test]
+    fn sweep_is_centred() {
+        for law in LAWS {
+            for (min_delay, max_delay) in [(10.0, 30.0), (0.0, 200.0), (50.0, 60.0), (40.0, 40.0)] {
+                let centre = law.map(0.0, min_delay, max_delay);
+                assert!((centre - (min_delay + max_delay) / 2.0).abs() < 1e-4, "{}: {} to {} is centred on {}", law.to_index(), min_delay, max_delay, centre);
+            }
+        }
+    }
+
+    #[test]
+    fn sweep_reaches_both_ends() {
+        for law in LAWS {
+            let (min_delay, max_delay) = (10.0, 30.0);
+            assert!((law.map(-1.0, min_delay, max_delay) - min_delay).abs() < 1e-4);
+            assert!((law.map(1.0, min_delay, max_delay) - max_delay).abs() < 1e-4);
+            let mut previous = 0.0;
+            for i in 0..=100 {
+                let delay = law.map(i as f32 / 50.0 - 1.0, min_delay, max_delay);
+                assert!(delay >= previous, "{}: the sweep turns back at {}", law.to_index(), i);
+                previous = delay;
+            }
+        }
+    }
+}
@@ -1,4 +1,9 @@
+use delay::Interpolation;
+use filter::{FEEDBACK_HPF_MIN, FEEDBACK_LPF_MAX};
+use barberpole::{Direction, NoteLength};
+use lfo::SweepLaw;
 use nih_plug::prelude::*;
+use nih_plug::wrapper::state::{ParamValue, PluginState};
 use std::{sync::{Arc, mpsc::channel}, collections::VecDeque, env};
 
 use nih_plug::prelude::*;
@@ -10,6 +15,7 @@ mod editor;
 mod filter;
 mod delayingallpass;
 mod flanger;
+mod barberpole;
 
 const MAX_BLOCK_SIZE: usize = 64;
 
@@ -17,6 +23,7 @@ struct FlangerPlugin {
     params: Arc<FlangerPluginParams>,
     sample_rate: f32,
     flanger: flanger::Flanger,
+    prev_through_zero: bool,
 }
 
 #[derive(Params)]
@@ -39,8 +46,41 @@ struct FlangerPluginParams {
     #[id = "dry"]
     dry: FloatParam,
 
-    #[id = "stereo"]
-    stereo: BoolParam,
+    #[id = "stereo_phase"]
+    stereo_phase: FloatParam,
+
+    #[id = "quality"]
+    quality: EnumParam<Interpolation>,
+
+    #[id = "through_zero"]
+    through_zero: BoolParam,
+
+    #[id = "manual"]
+    manual: FloatParam,
+
+    #[id = "freeze"]
+    freeze: BoolParam,
+
+    #[id = "sweep_law"]
+    sweep_law: EnumParam<SweepLaw>,
+
+    #[id = "barber_pole"]
+    barber_pole: BoolParam,
+
+    #[id = "direction"]
+    direction: EnumParam<Direction>,
+
+    #[id = "tempo_sync"]
+    tempo_sync: BoolParam,
+
+    #[id = "sync_length"]
+    sync_length: EnumParam<NoteLength>,
+
+    #[id = "feedback_lpf"]
+    feedback_lpf: FloatParam,
+
+    #[id = "feedback_hpf"]
+    feedback_hpf: FloatParam,
 }
 
 impl Default for FlangerPlugin {
@@ -49,6 +89,7 @@ impl Default for FlangerPlugin {
             params: Arc::new(FlangerPluginParams::default()),
             sample_rate: 44100.0,
             flanger: flanger::Flanger::new(44100.0),
+            prev_through_zero: false,
         }
     }
 }
@@ -58,7 +99,7 @@ impl Default for FlangerPluginParams {
         Self {
             editor_state: editor::default_state(),
 
-            depth: FloatParam::new("Depth", 0.1, FloatRange::Linear { min: 0.0, max: 1.0 })
+            depth: FloatParam::new("Depth", 0.7, FloatRange::Linear { min: 0.0, max: 1.0 })
             .with_smoother(SmoothingStyle::Linear(15.0))
             .with_unit("%")
             .with_value_to_string(formatters::v2s_f32_percentage(2)),
@@ -68,7 +109,8 @@ impl Default for FlangerPluginParams {
             .with_unit("Hz")
             .with_value_to_string(formatters::v2s_f32_rounded(2)),
 
-            feedback: FloatParam::new("Feedback", 0.0, FloatRange::Linear { min: 0.0, max: 0.999 })
+            // negative feedback flips the repeats, giving the hollow odd-harmonic comb
+            feedback: FloatParam::new("Feedback", 0.0, FloatRange::Linear { min: -0.99, max: 0.99 })
             .with_smoother(SmoothingStyle::Linear(15.0))
             .with_unit("%")
             .with_value_to_string(formatters::v2s_f32_percentage(2))
@@ -86,7 +128,44 @@ impl Default for FlangerPluginParams {
             .with_value_to_string(formatters::v2s_f32_percentage(2))
             .with_string_to_value(formatters::s2v_f32_percentage()),
 
-            stereo: BoolParam::new("Stereo", false),
+            stereo_phase: FloatParam::new("Stereo Phase", 0.0, FloatRange::Linear { min: 0.0, max: 360.0 })
+            .with_smoother(SmoothingStyle::Linear(15.0))
+            .with_unit("°")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            quality: EnumParam::new("Quality", Interpolation::Linear),
+
+            through_zero: BoolParam::new("Through Zero", false),
+
+            // the centre of the old full depth sweep, 0.75 to 15 ms
+            manual: FloatParam::new("Manual", 7.9, FloatRange::Skewed { min: 0.1, max: 15.0, factor: 0.4 })
+            .with_smoother(SmoothingStyle::Logarithmic(15.0))
+            .with_unit("ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            freeze: BoolParam::new("Freeze", false),
+
+            sweep_law: EnumParam::new("Sweep Law", SweepLaw::Linear),
+
+            barber_pole: BoolParam::new("Barber Pole", false),
+
+            direction: EnumParam::new("Direction", Direction::Up),
+
+            tempo_sync: BoolParam::new("Tempo Sync", false),
+
+            sync_length: EnumParam::new("Sync Length", NoteLength::OneBar),
+
+            // off at the top of the range
+            feedback_lpf: FloatParam::new("Feedback Low-Pass", FEEDBACK_LPF_MAX, FloatRange::Skewed { min: 200.0, max: FEEDBACK_LPF_MAX, factor: 0.3 })
+            .with_smoother(SmoothingStyle::Logarithmic(15.0))
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            // off at the bottom of the range
+            feedback_hpf: FloatParam::new("Feedback High-Pass", FEEDBACK_HPF_MIN, FloatRange::Skewed { min: FEEDBACK_HPF_MIN, max: 2000.0, factor: 0.3 })
+            .with_smoother(SmoothingStyle::Logarithmic(15.0))
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
         }
     }
 }
@@ -96,7 +175,7 @@ impl Plugin for FlangerPlugin {
     const VENDOR: &'static str = "Hubert Łabuda";
     const URL: &'static str = "https://www.linkedin.com/in/hubert-%C5%82abuda/";
     const EMAIL: &'static str = "none";
-    const VERSION: &'static str = "none";
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
     // The first audio IO layout is used as the default. The other layouts may be selected either
     // explicitly or automatically by the host or the user depending on the plugin API/backend.
@@ -122,6 +201,25 @@ impl Plugin for FlangerPlugin {
     // tasks.
     type BackgroundTask = ();
 
+    // sessions saved before the plugin reported its version have no Manual, Depth swept the
+    // delay from 5% to 100% of depth * 15 ms. the same range is a centre of 52.5% of that
+    // with a relative depth of 0.475 / 0.525. they also had a Stereo switch, which ran the
+    // right LFO a quarter cycle ahead
+    fn filter_state(state: &mut PluginState) {
+        if state.version != "none" {
+            return;
+        }
+        if let Some(&ParamValue::F32(depth)) = state.params.get("depth") {
+            let manual = (depth * 15.0 * 0.525).max(0.1);
+            state.params.insert(String::from("manual"), ParamValue::F32(manual));
+            state.params.insert(String::from("depth"), ParamValue::F32(0.475 / 0.525));
+        }
+        if let Some(ParamValue::Bool(stereo)) = state.params.remove("stereo") {
+            let stereo_phase = if stereo { 90.0 } else { 0.0 };
+            state.params.insert(String::from("stereo_phase"), ParamValue::F32(stereo_phase));
+        }
+    }
+
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
@@ -137,12 +235,16 @@ impl Plugin for FlangerPlugin {
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
         self.flanger.resize_buffers(self.sample_rate);
+        // the dry path is delayed in through-zero mode
+        self.prev_through_zero = self.params.through_zero.value();
+        _context.set_latency_samples(if self.prev_through_zero { self.flanger.through_zero_latency() } else { 0 });
         true
     }
 
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.flanger.reset();
     }
 
     fn process(
@@ -156,18 +258,55 @@ impl Plugin for FlangerPlugin {
         // 1. outer loop iterates block-size times
         // 2. inner loop iterates channel-size times. 
 
+        let through_zero = self.params.through_zero.value();
+        if through_zero != self.prev_through_zero {
+            self.prev_through_zero = through_zero;
+            _context.set_latency_samples(if through_zero { self.flanger.through_zero_latency() } else { 0 });
+        }
+
+        // with tempo sync the LFO does one cycle per note length, falls back to 120 BPM when
+        // the host doesn't report a tempo. while the host is playing the phase follows the
+        // song position, so the sweep lands on the same beat on every playback
+        let tempo_sync = self.params.tempo_sync.value();
+        let sync_length = self.params.sync_length.value();
+        let transport = _context.transport();
+        let synced_rate = sync_length.rate(transport.tempo.unwrap_or(120.0) as f32);
+        let synced_phase = match transport.pos_beats() {
+            Some(pos_beats) if tempo_sync && transport.playing => {
+                Some((pos_beats / sync_length.beats() as f64).rem_euclid(1.0) as f32)
+            },
+            _ => None,
+        };
+        let barber_pole = self.params.barber_pole.value();
+        let direction = self.params.direction.value();
+        let sweep_law = self.params.sweep_law.value();
+
         for (i, channel_samples) in buffer.iter_samples().enumerate() {
             // Smoothing is optionally built into the parameters themselves
             // let gain = self.params.gain.smoothed.next();
 
             let depth = self.params.depth.smoothed.next();
             let rate = self.params.rate.smoothed.next();
+            let rate = if tempo_sync { synced_rate } else { rate };
             let feedback = self.params.feedback.smoothed.next();
             let wet = self.params.wet.smoothed.next();
             let dry = self.params.dry.smoothed.next();
-            let stereo = self.params.stereo.value();
-
-            self.flanger.set_params(depth, rate, feedback, wet, dry, stereo);
+            let stereo_phase = self.params.stereo_phase.smoothed.next();
+            let quality = self.params.quality.value();
+            let manual = self.params.manual.smoothed.next();
+            let freeze = self.params.freeze.value();
+            let feedback_lpf = self.params.feedback_lpf.smoothed.next();
+            let feedback_hpf = self.params.feedback_hpf.smoothed.next();
+
+            self.flanger.set_params(depth, rate, feedback, wet, dry, stereo_phase, quality, through_zero, manual, freeze);
+            self.flanger.set_feedback_filters(feedback_lpf, feedback_hpf);
+            self.flanger.set_barber_pole(barber_pole, direction, quality);
+            self.flanger.set_sweep_law(sweep_law);
+            if i == 0 {
+                if let Some(phase) = synced_phase {
+                    self.flanger.sync_lfo(phase);
+                }
+            }
 
             for (num, sample) in channel_samples.into_iter().enumerate() {
                 if num == 0 {
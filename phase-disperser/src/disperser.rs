@@ -31,6 +31,13 @@ impl Disperser {
         self.output_hpf.second_order_hpf_coefficients(sample_rate, 30.0, 0.707)
     }
 
+    pub fn reset(&mut self) {
+        for bq in self.allpasses.iter_mut() {
+            bq.reset_filter();
+        }
+        self.output_hpf.reset_filter();
+    }
+
     pub fn set_params(&mut self, frequency: f32, q: f32, spread: f32, amount: u32) {
         //for (i, bq) in self.allpasses.iter_mut().enumerate() {
         for i in 0..(self.amount as usize) {
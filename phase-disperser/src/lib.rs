@@ -122,6 +122,7 @@ impl Plugin for EffectPlugin {
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.disperser.reset();
     }
 
     fn process(
@@ -63,7 +63,7 @@ struct Data {
 impl Model for Data {}
 
 pub(crate) fn default_state() -> Arc<ViziaState> {
-    ViziaState::new(|| (350, 350))
+    ViziaState::new(|| (350, 590))
 }
 
 pub(crate) fn create(
@@ -109,9 +109,28 @@ pub(crate) fn create(
                     ParamKnob::new(cx, Data::phaser_data, |params| &params.offset, false)
                     .height(Pixels(30.0));
 
-                    ParamKnob::new(cx, Data::phaser_data, |params| &params.intensity, false)
+                    ParamKnob::new(cx, Data::phaser_data, |params| &params.mix, false)
                     .height(Pixels(30.0));
                 }).col_between(Pixels(15.0));
+                HStack::new(cx, |cx| {
+                    ParamKnob::new(cx, Data::phaser_data, |params| &params.stereo_phase, false)
+                    .height(Pixels(30.0));
+
+                    ParamKnob::new(cx, Data::phaser_data, |params| &params.min_frequency, false)
+                    .height(Pixels(30.0));
+
+                    ParamKnob::new(cx, Data::phaser_data, |params| &params.max_frequency, false)
+                    .height(Pixels(30.0));
+                }).col_between(Pixels(15.0));
+                HStack::new(cx, |cx| {
+                    ParamKnob::new(cx, Data::phaser_data, |params| &params.feedback_tap, false)
+                    .height(Pixels(30.0));
+
+                    ParamButton::new(cx, Data::phaser_data, |params| &params.wet_invert)
+                    .height(Pixels(30.0))
+                    .space(Stretch(1.0))
+                    .bottom(Percentage(51.0));
+                }).col_between(Pixels(15.0));
             
             }).row_between(Pixels(0.0))
             .child_left(Stretch(1.0))
@@ -1,4 +1,5 @@
 use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
 
 use nih_plug::prelude::Enum;
 
@@ -299,9 +300,12 @@ impl BiquadFilter {
     }
     
     pub fn first_order_allpass_coefficients(&mut self, sample_rate: f32, cutoff: f32) {
-        let alpha = 
-        ((PI * cutoff / sample_rate).tan() - 1.0) 
-        / ((PI * cutoff / sample_rate).tan() + 1.0);
+        self.set_first_order_allpass(first_order_allpass_alpha(sample_rate, cutoff));
+    }
+
+    // first order allpass from a precalculated alpha, so modulated filters can interpolate
+    // alpha instead of calling tan() every sample
+    pub fn set_first_order_allpass(&mut self, alpha: f32) {
         let a0 = alpha;
         let a1 = 1.0;
         let a2 = 0.0;
@@ -422,4 +426,136 @@ impl BiquadFilter {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+pub fn first_order_allpass_alpha(sample_rate: f32, cutoff: f32) -> f32 {
+    let k = (PI * cutoff / sample_rate).tan();
+    (k - 1.0) / (k + 1.0)
+}
+
+// sample type of TdfBiquadFilter, f64 keeps low cutoffs at high sample rates accurate
+pub trait FilterPrecision: Copy + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn from_f64(x: f64) -> Self;
+    fn from_f32(x: f32) -> Self;
+    fn to_f32(self) -> f32;
+}
+
+impl FilterPrecision for f32 {
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+}
+
+impl FilterPrecision for f64 {
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn from_f32(x: f32) -> Self {
+        x as f64
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+}
+
+// transposed direct form II biquad. only two state values per channel, and with f64
+// state and coefficients the poles of very low cutoffs stay where they should be.
+// coefficients are always calculated in f64
+#[derive(Clone, Copy)]
+pub struct TdfBiquadFilter<T: FilterPrecision> {
+    a0: T,
+    a1: T,
+    a2: T,
+    b0: T,
+    b1: T,
+
+    l_s1: T,
+    l_s2: T,
+    r_s1: T,
+    r_s2: T,
+
+    sample_rate: f32,
+}
+
+impl<T: FilterPrecision> TdfBiquadFilter<T> {
+    pub fn new() -> Self {
+        let zero = T::from_f64(0.0);
+        Self {
+            a0: T::from_f64(1.0),
+            a1: zero,
+            a2: zero,
+            b0: zero,
+            b1: zero,
+            l_s1: zero,
+            l_s2: zero,
+            r_s1: zero,
+            r_s2: zero,
+            sample_rate: 44100.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn reset_filter(&mut self) {
+        let zero = T::from_f64(0.0);
+        self.l_s1 = zero;
+        self.l_s2 = zero;
+        self.r_s1 = zero;
+        self.r_s2 = zero;
+    }
+
+    pub fn process_left(&mut self, x: f32) -> f32 {
+        let x = T::from_f32(x);
+        let y = self.a0 * x + self.l_s1;
+        self.l_s1 = self.a1 * x - self.b0 * y + self.l_s2;
+        self.l_s2 = self.a2 * x - self.b1 * y;
+        y.to_f32()
+    }
+
+    pub fn process_right(&mut self, x: f32) -> f32 {
+        let x = T::from_f32(x);
+        let y = self.a0 * x + self.r_s1;
+        self.r_s1 = self.a1 * x - self.b0 * y + self.r_s2;
+        self.r_s2 = self.a2 * x - self.b1 * y;
+        y.to_f32()
+    }
+
+    fn set_coefficients(&mut self, a0: f64, a1: f64, a2: f64, b0: f64, b1: f64) {
+        self.a0 = T::from_f64(a0);
+        self.a1 = T::from_f64(a1);
+        self.a2 = T::from_f64(a2);
+        self.b0 = T::from_f64(b0);
+        self.b1 = T::from_f64(b1);
+    }
+
+    // same designs as BiquadFilter
+    pub fn second_order_lpf_coefficients(&mut self, cutoff: f32, q: f32) {
+        let o = 2.0 * std::f64::consts::PI * cutoff as f64 / self.sample_rate as f64;
+        let d = 1.0 / q as f64;
+        let b = 0.5 * ((1.0 - (d / 2.0) * o.sin()) / (1.0 + (d / 2.0) * o.sin()));
+        let y = (0.5 + b) * o.cos();
+        let a0 = (0.5 + b - y) / 2.0;
+        self.set_coefficients(a0, 2.0 * a0, a0, -2.0 * y, 2.0 * b);
+    }
+
+    pub fn second_order_hpf_coefficients(&mut self, cutoff: f32, q: f32) {
+        let o = 2.0 * std::f64::consts::PI * cutoff as f64 / self.sample_rate as f64;
+        let d = 1.0 / q as f64;
+        let b = 0.5 * ((1.0 - (d / 2.0) * o.sin()) / (1.0 + (d / 2.0) * o.sin()));
+        let y = (0.5 + b) * o.cos();
+        let a0 = (0.5 + b + y) / 2.0;
+        self.set_coefficients(a0, -2.0 * a0, a0, -2.0 * y, 2.0 * b);
+    }
+}
@@ -1,11 +1,11 @@
 use std::{f32::consts::PI, ops::Range};
 
-use rand::Rng;
-
 #[derive(Clone)]
 pub struct LFO {
     pub rate: f32,
     phase: f32,
+    // phase the LFO goes back to on reset
+    initial_phase: f32,
     pub sample_rate: f32
 }
 
@@ -15,15 +15,7 @@ impl LFO {
             sample_rate,
             rate,
             phase: 0.0,
-        }
-    }
-
-    pub fn new_random_phase(sample_rate: f32, rate: f32) -> Self {
-        let mut rng = rand::thread_rng();
-        Self {
-            sample_rate,
-            rate,
-            phase: rng.gen_range(0.0..(2.0 * PI))
+            initial_phase: 0.0,
         }
     }
 
@@ -31,7 +23,8 @@ impl LFO {
         Self {
             sample_rate,
             rate,
-            phase
+            phase,
+            initial_phase: phase,
         }
     }
 
@@ -40,6 +33,12 @@ impl LFO {
         self.phase.sin()
     }
 
+    /// value of the LFO shifted by an offset in radians, for deriving another channel's
+    /// modulation from this one. Values of <-1, 1>
+    pub fn next_value_with_offset(&self, offset: f32) -> f32 {
+        (self.phase + offset).sin()
+    }
+
     pub fn next_value_range(&mut self, range: Range<f32>) -> f32 {
         let value = self.next_value();
         let scaled = (value + 1.0) / 2.0;
@@ -47,10 +46,14 @@ impl LFO {
         scaled
     }
 
+    pub fn reset(&mut self) {
+        self.phase = self.initial_phase;
+    }
+
     pub fn update_lfo(&mut self) {
         self.phase += 2.0 * std::f32::consts::PI * self.rate / self.sample_rate;
         if self.phase > 2.0 * PI {
             self.phase -= 2.0 * PI;
         }
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,7 @@
 use filter::FilterType;
+use phaser::FeedbackTap;
 use nih_plug::prelude::*;
+use nih_plug::wrapper::state::{ParamValue, PluginState};
 use std::{sync::{Arc, mpsc::channel}, collections::VecDeque, env};
 
 use nih_plug::prelude::*;
@@ -17,7 +19,7 @@ const MAX_BLOCK_SIZE: usize = 64;
 pub struct PhaserPlugin {
     params: Arc<PhaserPluginParams>,
     phaser: phaser::Phaser,
-    output_hpf: filter::BiquadFilter,
+    output_hpf: filter::TdfBiquadFilter<f64>,
     sample_rate: f32,
 }
 
@@ -41,8 +43,24 @@ pub struct PhaserPluginParams {
     #[id = "offset"]
     offset: FloatParam,
 
+    // keeps the id of the old Intensity parameter, so saved sessions and automation still find it
     #[id = "intensity"]
-    intensity: FloatParam,
+    mix: FloatParam,
+
+    #[id = "feedback_tap"]
+    feedback_tap: EnumParam<FeedbackTap>,
+
+    #[id = "wet_invert"]
+    wet_invert: BoolParam,
+
+    #[id = "stereo_phase"]
+    stereo_phase: FloatParam,
+
+    #[id = "min_frequency"]
+    min_frequency: FloatParam,
+
+    #[id = "max_frequency"]
+    max_frequency: FloatParam,
 }
 
 impl Default for PhaserPlugin {
@@ -51,7 +69,7 @@ impl Default for PhaserPlugin {
             params: Arc::new(PhaserPluginParams::default()),
             phaser: phaser::Phaser::new(44100.0),
             sample_rate: 44100.0,
-            output_hpf: filter::BiquadFilter::new(),
+            output_hpf: filter::TdfBiquadFilter::new(),
         }
     }
 }
@@ -75,12 +93,12 @@ impl Default for PhaserPluginParams {
             .with_unit("Hz")
             .with_value_to_string(formatters::v2s_f32_rounded(2)),
 
-            feedback: FloatParam::new("Feedback", 0.0, FloatRange::Linear { min: 0.0, max: 0.9 })
+            feedback: FloatParam::new("Feedback", 0.0, FloatRange::Linear { min: -0.9, max: 0.9 })
             .with_unit("%")
             .with_value_to_string(formatters::v2s_f32_percentage(1))
             .with_string_to_value(formatters::s2v_f32_percentage()),
 
-            stages: IntParam::new("Stages", 3, IntRange::Linear { min: 1, max: 3 })
+            stages: IntParam::new("Stages", 6, IntRange::Linear { min: phaser::MIN_STAGES as i32, max: phaser::MAX_STAGES as i32 })
             .with_value_to_string(v2s_i32()),
 
             offset: FloatParam::new("Offset", 0.0, FloatRange::Linear { min: -1.0, max: 1.0 })
@@ -88,10 +106,27 @@ impl Default for PhaserPluginParams {
             .with_value_to_string(formatters::v2s_f32_percentage(1))
             .with_string_to_value(formatters::s2v_f32_percentage()),
 
-            intensity: FloatParam::new("Intensity", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            // 50% is the classic phaser, equal parts dry and phase shifted for the deepest notches
+            mix: FloatParam::new("Mix", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
             .with_unit("%")
             .with_value_to_string(formatters::v2s_f32_percentage(1))
             .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            feedback_tap: EnumParam::new("Feedback Tap", FeedbackTap::LastStage),
+
+            wet_invert: BoolParam::new("Wet Invert", false),
+
+            stereo_phase: FloatParam::new("Stereo Phase", 0.0, FloatRange::Linear { min: 0.0, max: 360.0 })
+            .with_unit("°")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            min_frequency: FloatParam::new("Min Frequency", 20.0, FloatRange::Skewed { min: 20.0, max: 20000.0, factor: 0.25 })
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+
+            max_frequency: FloatParam::new("Max Frequency", 20000.0, FloatRange::Skewed { min: 20.0, max: 20000.0, factor: 0.25 })
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(1))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
         }
     }
 }
@@ -101,7 +136,7 @@ impl Plugin for PhaserPlugin {
     const VENDOR: &'static str = "Hubert Łabuda";
     const URL: &'static str = "https://www.linkedin.com/in/hubert-%C5%82abuda/";
     const EMAIL: &'static str = "none";
-    const VERSION: &'static str = "none";
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
     // The first audio IO layout is used as the default. The other layouts may be selected either
     // explicitly or automatically by the host or the user depending on the plugin API/backend.
@@ -127,6 +162,29 @@ impl Plugin for PhaserPlugin {
     // tasks.
     type BackgroundTask = ();
 
+    // sessions saved before the plugin reported its version stored Intensity, which mixed in
+    // half as much of the phase shifted signal as the same Mix value does now. their Stages
+    // counted pairs of allpasses, and the sweep ran from 16 Hz up to the top of the last
+    // active pair (3.3, 9.8 or 20.48 kHz). the range is seeded from that, as far as Min and
+    // Max Frequency reach
+    fn filter_state(state: &mut PluginState) {
+        if state.version == "none" {
+            if let Some(ParamValue::F32(intensity)) = state.params.get_mut("intensity") {
+                *intensity /= 2.0;
+            }
+            if let Some(ParamValue::I32(stages)) = state.params.get_mut("stages") {
+                let max_frequency = match *stages {
+                    1 => 3300.0,
+                    2 => 9800.0,
+                    _ => 20000.0,
+                };
+                *stages *= 2;
+                state.params.insert(String::from("min_frequency"), ParamValue::F32(20.0));
+                state.params.insert(String::from("max_frequency"), ParamValue::F32(max_frequency));
+            }
+        }
+    }
+
     fn params(&self) -> Arc<dyn Params> {
         self.params.clone()
     }
@@ -139,7 +197,7 @@ impl Plugin for PhaserPlugin {
     ) -> bool {
         self.sample_rate = _buffer_config.sample_rate as f32;
         self.output_hpf.set_sample_rate(self.sample_rate);
-        self.output_hpf.second_order_hpf_coefficients(self.sample_rate, 25.0, 0.8);
+        self.output_hpf.second_order_hpf_coefficients(25.0, 0.8);
         // Resize buffers and perform other potentially expensive initialization operations here.
         // The `reset()` function is always called right after this function. You can remove this
         // function if you do not need it.
@@ -150,6 +208,8 @@ impl Plugin for PhaserPlugin {
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
         // allocate. You can remove this function if you do not need it.
+        self.phaser.reset();
+        self.output_hpf.reset_filter();
     }
 
     fn process(
@@ -172,9 +232,14 @@ impl Plugin for PhaserPlugin {
             let feedback = self.params.feedback.smoothed.next();
             let stages = self.params.stages.value();
             let offset = self.params.offset.smoothed.next();
-            let intensity = self.params.intensity.smoothed.next();
+            let mix = self.params.mix.smoothed.next();
+            let feedback_tap = self.params.feedback_tap.value();
+            let wet_invert = self.params.wet_invert.value();
+            let stereo_phase = self.params.stereo_phase.smoothed.next();
+            let min_frequency = self.params.min_frequency.smoothed.next();
+            let max_frequency = self.params.max_frequency.smoothed.next();
 
-            self.phaser.set_params(rate, depth, stages as usize, offset, feedback, intensity);
+            self.phaser.set_params(rate, depth, stages as usize, offset, feedback, mix, stereo_phase, min_frequency, max_frequency, feedback_tap, wet_invert);
 
             for (num, sample) in channel_samples.into_iter().enumerate() {
                 if num == 0 {
@@ -182,7 +247,7 @@ impl Plugin for PhaserPlugin {
                     *sample = self.output_hpf.process_left(*sample);
                 } else {
                     *sample = self.phaser.process_right(*sample);
-                    *sample = self.output_hpf.process_left(*sample);
+                    *sample = self.output_hpf.process_right(*sample);
                 }
             }
         }
@@ -1,15 +1,105 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, ops::Range};
 
-use crate::{delayingallpass::DelayingAllPass, lfo, filter::BiquadFilter};
+use nih_plug::prelude::Enum;
 
-const PHASER_DELAYS: [f32; 12] = [
-    16.0, 1600.0, 
-    33.0, 3300.0, 
-    48.0, 4800.0,
-    98.0, 9800.0,
-    160.0, 16000.0,
-    260.0, 20480.0,
-    ];
+use crate::{delayingallpass::DelayingAllPass, lfo, filter::{BiquadFilter, first_order_allpass_alpha}};
+
+pub const MIN_STAGES: usize = 2;
+pub const MAX_STAGES: usize = 24;
+// the allpass coefficients are calculated every CONTROL_INTERVAL samples and interpolated
+// in between, so tan() and powf() run once per stage per interval instead of every sample
+const CONTROL_INTERVAL: usize = 32;
+
+// where the feedback is taken from
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackTap {
+    LastStage,
+    // after stage N/2 rounded down, so with an odd count the tap sits just before the middle
+    // stage (after stage 2 of 5)
+    HalfwayStage,
+}
+
+impl Enum for FeedbackTap {
+    fn variants() -> &'static [&'static str] {
+        &[
+            "Last Stage",
+            "Stage N/2 (Rounded Down)",
+        ]
+    }
+
+    fn ids() -> Option<&'static [&'static str]> {
+        Some(&[
+            "last_stage",
+            "halfway_stage",
+        ])
+    }
+
+    fn to_index(self) -> usize {
+        match self {
+            FeedbackTap::LastStage => 0,
+            FeedbackTap::HalfwayStage => 1,
+        }
+    }
+
+    fn from_index(index: usize) -> Self {
+        match index {
+            0 => FeedbackTap::LastStage,
+            1 => FeedbackTap::HalfwayStage,
+            _ => panic!("Invalid feedback tap index."),
+        }
+    }
+}
+
+// interpolated allpass coefficients of one channel
+#[derive(Clone)]
+struct StageModulation {
+    alphas: [f32; MAX_STAGES],
+    steps: [f32; MAX_STAGES],
+    countdown: usize,
+    // starts from the current sweep instead of ramping from stale coefficients, after a reset
+    snap: bool,
+}
+
+impl StageModulation {
+    fn new() -> Self {
+        Self {
+            alphas: [0.0; MAX_STAGES],
+            steps: [0.0; MAX_STAGES],
+            countdown: 0,
+            snap: true,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.countdown = 0;
+        self.snap = true;
+    }
+
+    // ramps towards the targets over the next CONTROL_INTERVAL samples
+    fn ramp_to(&mut self, targets: &[f32]) {
+        self.snap = false;
+        for (i, target) in targets.iter().enumerate() {
+            self.steps[i] = (target - self.alphas[i]) / CONTROL_INTERVAL as f32;
+        }
+        self.countdown = CONTROL_INTERVAL;
+    }
+
+    // stages that were switched off still hold the coefficients and steps they had back then,
+    // added stages start from the current targets and hold them until the next ramp
+    fn start_stages(&mut self, stages: Range<usize>, targets: &[f32]) {
+        for i in stages {
+            self.alphas[i] = targets[i];
+            self.steps[i] = 0.0;
+        }
+    }
+
+    fn advance(&mut self, stages: usize) {
+        for i in 0..stages {
+            self.alphas[i] += self.steps[i];
+        }
+        self.countdown -= 1;
+    }
+}
 
 #[derive (Clone)]
 pub struct Phaser {
@@ -19,11 +109,23 @@ pub struct Phaser {
     feedback: f32,
     rate: f32,
     sample_rate: f32,
+    // the right channel reads the same LFO shifted by stereo_phase, so the channels can't drift apart
     lfo: lfo::LFO,
+    stereo_phase: f32,
     depth: f32,
     stages: usize,
+    // every allpass sweeps its own part of the min..max frequency range
+    min_frequency: f32,
+    max_frequency: f32,
+    stage_ranges: [(f32, f32); MAX_STAGES],
+    left_modulation: StageModulation,
+    right_modulation: StageModulation,
     offset: f32,
-    intensity: f32,
+    feedback_tap: FeedbackTap,
+    // 0.5 is the classic equal mix with the deepest notches, 1 is only the phase shifted signal
+    mix: f32,
+    // flips the phase shifted signal, the notches turn into peaks
+    wet_invert: bool,
 }
 
 impl Phaser {
@@ -35,11 +137,9 @@ impl Phaser {
             right_feedback_buffer.push_front(0.0);
         }
 
-        let mut allpasses: Vec<BiquadFilter> = Vec::new();
-        for i in 0..6 {
-            let mut allpass = BiquadFilter::new();
-            allpass.first_order_allpass_coefficients(sample_rate, PHASER_DELAYS[i*2]);
-            allpasses.push(allpass);
+        let mut allpasses: Vec<BiquadFilter> = Vec::with_capacity(MAX_STAGES);
+        for _ in 0..MAX_STAGES {
+            allpasses.push(BiquadFilter::new());
         }
 
         let lfo = lfo::LFO::new(sample_rate, 0.2);
@@ -52,10 +152,18 @@ impl Phaser {
             rate: 0.0,
             sample_rate: 44100.0,
             lfo: lfo,
+            stereo_phase: 0.0,
             depth: 0.0,
             stages: 0,
+            min_frequency: 0.0,
+            max_frequency: 0.0,
+            stage_ranges: [(0.0, 0.0); MAX_STAGES],
+            left_modulation: StageModulation::new(),
+            right_modulation: StageModulation::new(),
             offset: 0.0,
-            intensity: 0.0,
+            feedback_tap: FeedbackTap::LastStage,
+            mix: 0.5,
+            wet_invert: false,
         }
     }
 
@@ -70,36 +178,73 @@ impl Phaser {
         self.lfo.sample_rate = sample_rate;
     }
 
-    pub fn set_params(&mut self, rate: f32, depth: f32, stages: usize, offset: f32, feedback: f32, intensity: f32) {
+    // clears the feedback and allpass state and restarts the LFO
+    pub fn reset(&mut self) {
+        self.left_feedback_buffer.iter_mut().for_each(|x| *x = 0.0);
+        self.right_feedback_buffer.iter_mut().for_each(|x| *x = 0.0);
+        for allpass in self.allpasses.iter_mut() {
+            allpass.reset_filter();
+        }
+        self.lfo.reset();
+        self.left_modulation.reset();
+        self.right_modulation.reset();
+    }
+
+    pub fn set_params(&mut self, rate: f32, depth: f32, stages: usize, offset: f32, feedback: f32, mix: f32, stereo_phase: f32, min_frequency: f32, max_frequency: f32, feedback_tap: FeedbackTap, wet_invert: bool) {
         self.rate = rate;
         self.lfo.rate = rate;
         self.depth = depth;
         self.offset = offset;
-        self.stages = stages;
-        self.intensity = intensity;
+        self.stereo_phase = stereo_phase.to_radians();
+        let stages = stages.clamp(MIN_STAGES, MAX_STAGES);
+        if stages != self.stages || min_frequency != self.min_frequency || max_frequency != self.max_frequency {
+            let added_stages = self.stages..stages;
+            self.stages = stages;
+            self.min_frequency = min_frequency;
+            self.max_frequency = max_frequency;
+            self.update_stage_ranges();
+            if !added_stages.is_empty() {
+                let left_targets = self.stage_alphas(self.lfo.next_value_with_offset(0.0));
+                let right_targets = self.stage_alphas(self.lfo.next_value_with_offset(self.stereo_phase));
+                self.left_modulation.start_stages(added_stages.clone(), &left_targets);
+                self.right_modulation.start_stages(added_stages, &right_targets);
+            }
+        }
+        self.mix = mix;
         self.feedback = feedback;
+        self.feedback_tap = feedback_tap;
+        self.wet_invert = wet_invert;
     }
 
     pub fn process_left(&mut self, x: f32) -> f32 {
         let y: f32;
         let mut phased_signal = 
             x + self.feedback * self.left_feedback_buffer[0];
-        
-
-        for i in 0..(self.stages * 2) {
-            self.allpasses[i].first_order_allpass_coefficients(
-                self.sample_rate, 
-                lerp(PHASER_DELAYS[2*i], 
-                PHASER_DELAYS[2*i+1],
-                (self.lfo.next_value() * self.depth + self.offset).clamp(-1.0, 1.0) / 2.0 + 0.5
-            ));
+
+        let tap_stage = self.feedback_tap_stage();
+        let mut feedback_signal = 0.0;
+        if self.left_modulation.countdown == 0 {
+            if self.left_modulation.snap {
+                let current = self.stage_alphas(self.lfo.next_value_with_offset(0.0));
+                self.left_modulation.start_stages(0..self.stages, &current);
+            }
+            let lfo_value = self.lfo.next_value_with_offset(self.control_lookahead());
+            let targets = self.stage_alphas(lfo_value);
+            self.left_modulation.ramp_to(&targets[..self.stages]);
+        }
+        // the channels share the allpasses but not the coefficients
+        for i in 0..self.stages {
+            self.allpasses[i].set_first_order_allpass(self.left_modulation.alphas[i]);
             phased_signal = self.allpasses[i].process_left(phased_signal);
+            if i == tap_stage {
+                feedback_signal = phased_signal;
+            }
         }
+        self.left_modulation.advance(self.stages);
         self.left_feedback_buffer.rotate_right(1);
-        self.left_feedback_buffer[0] = phased_signal;
+        self.left_feedback_buffer[0] = feedback_signal;
 
-        let x_gain = 1.0 - self.intensity / 2.0;
-        y = x_gain * x + self.intensity / 2.0 * phased_signal;
+        y = self.mix_output(x, phased_signal);
         y
     }
 
@@ -107,30 +252,230 @@ impl Phaser {
         let y: f32;
         let mut phased_signal = 
             x + self.feedback * self.right_feedback_buffer[0];
-        
-
-        for i in 0..(self.stages * 2) {
-            self.allpasses[i].first_order_allpass_coefficients(
-                self.sample_rate, 
-                lerp(PHASER_DELAYS[2*i], 
-                PHASER_DELAYS[2*i+1],
-                (self.lfo.next_value() * self.depth + self.offset).clamp(-1.0, 1.0) / 2.0 + 0.5
-            ));
+
+        let tap_stage = self.feedback_tap_stage();
+        let mut feedback_signal = 0.0;
+        if self.right_modulation.countdown == 0 {
+            if self.right_modulation.snap {
+                let current = self.stage_alphas(self.lfo.next_value_with_offset(self.stereo_phase));
+                self.right_modulation.start_stages(0..self.stages, &current);
+            }
+            let lfo_value = self.lfo.next_value_with_offset(self.stereo_phase + self.control_lookahead());
+            let targets = self.stage_alphas(lfo_value);
+            self.right_modulation.ramp_to(&targets[..self.stages]);
+        }
+        for i in 0..self.stages {
+            self.allpasses[i].set_first_order_allpass(self.right_modulation.alphas[i]);
             phased_signal = self.allpasses[i].process_right(phased_signal);
+            if i == tap_stage {
+                feedback_signal = phased_signal;
+            }
         }
+        self.right_modulation.advance(self.stages);
 
         self.right_feedback_buffer.rotate_right(1);
-        self.right_feedback_buffer[0] = phased_signal;
+        self.right_feedback_buffer[0] = feedback_signal;
 
         // do this once, in right channel since both channels share a common
         self.lfo.update_lfo();
-        let x_gain = 1.0 - self.intensity / 2.0;
-        y = x_gain * x + self.intensity / 2.0 * phased_signal;
+        y = self.mix_output(x, phased_signal);
         y
     }
-    
+
+    // the stages are staggered evenly in log frequency. each one sweeps half of the log
+    // range (a ratio of sqrt(max / min)), the first starts at min and the last ends at max
+    fn update_stage_ranges(&mut self) {
+        let min_frequency = self.min_frequency.min(self.max_frequency);
+        let ratio = self.max_frequency.max(self.min_frequency) / min_frequency;
+        let window = ratio.sqrt();
+        for i in 0..self.stages {
+            let low = min_frequency * window.powf(i as f32 / (self.stages - 1) as f32);
+            self.stage_ranges[i] = (low, low * window);
+        }
+    }
+
+    // index of the stage the feedback is taken after
+    fn feedback_tap_stage(&self) -> usize {
+        match self.feedback_tap {
+            FeedbackTap::LastStage => self.stages - 1,
+            FeedbackTap::HalfwayStage => self.stages / 2 - 1,
+        }
+    }
+
+    fn mix_output(&self, dry: f32, wet: f32) -> f32 {
+        let wet = if self.wet_invert { -wet } else { wet };
+        (1.0 - self.mix) * dry + self.mix * wet
+    }
+
+    // the coefficients are reached at the end of the interval, so the targets are taken from
+    // where the LFO will be by then (in radians ahead of the current phase)
+    fn control_lookahead(&self) -> f32 {
+        2.0 * std::f32::consts::PI * self.rate * CONTROL_INTERVAL as f32 / self.sample_rate
+    }
+
+    // allpass coefficients of every active stage for an LFO value
+    fn stage_alphas(&self, lfo_value: f32) -> [f32; MAX_STAGES] {
+        let position = self.sweep_position(lfo_value);
+        let mut alphas = [0.0; MAX_STAGES];
+        for i in 0..self.stages {
+            alphas[i] = first_order_allpass_alpha(self.sample_rate, self.stage_frequency(i, position));
+        }
+        alphas
+    }
+
+    // position of the sweep from 0 to 1
+    fn sweep_position(&self, lfo_value: f32) -> f32 {
+        (lfo_value * self.depth + self.offset).clamp(-1.0, 1.0) / 2.0 + 0.5
+    }
+
+    // the frequencies move by even ratios and stay below nyquist
+    fn stage_frequency(&self, stage: usize, position: f32) -> f32 {
+        let (low, high) = self.stage_ranges[stage];
+        (low * (high / low).powf(position)).min(0.45 * self.sample_rate)
+    }
 }
 
-pub fn lerp(a: f32, b: f32, t: f32) -> f32 {
-    a + (b - a) * t
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{hint::black_box, time::Instant};
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn phaser(stages: usize) -> Phaser {
+        let mut phaser = Phaser::new(SAMPLE_RATE);
+        phaser.resize_buffers(SAMPLE_RATE);
+        set_stages(&mut phaser, stages);
+        phaser
+    }
+
+    fn set_stages(phaser: &mut Phaser, stages: usize) {
+        phaser.set_params(1.5, 1.0, stages, 0.0, 0.6, 0.5, 90.0, 100.0, 8000.0, FeedbackTap::LastStage, false);
+    }
+
+    fn noise(length: usize) -> Vec<f32> {
+        let mut state: u32 = 1;
+        (0..length).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 - 0.5
+        }).collect()
+    }
+
+    // the previous version of process_left()/process_right(), with the coefficients
+    // calculated every sample
+    fn process_per_sample(phaser: &mut Phaser, x: f32, right: bool) -> f32 {
+        let offset = if right { phaser.stereo_phase } else { 0.0 };
+        let position = phaser.sweep_position(phaser.lfo.next_value_with_offset(offset));
+        let buffer = if right { &phaser.right_feedback_buffer } else { &phaser.left_feedback_buffer };
+        let mut phased_signal = x + phaser.feedback * buffer[0];
+        let tap_stage = phaser.feedback_tap_stage();
+        let mut feedback_signal = 0.0;
+        for i in 0..phaser.stages {
+            let frequency = phaser.stage_frequency(i, position);
+            let allpass = &mut phaser.allpasses[i];
+            allpass.first_order_allpass_coefficients(SAMPLE_RATE, frequency);
+            phased_signal = if right { allpass.process_right(phased_signal) } else { allpass.process_left(phased_signal) };
+            if i == tap_stage {
+                feedback_signal = phased_signal;
+            }
+        }
+        let buffer = if right { &mut phaser.right_feedback_buffer } else { &mut phaser.left_feedback_buffer };
+        buffer.rotate_right(1);
+        buffer[0] = feedback_signal;
+        if right {
+            phaser.lfo.update_lfo();
+        }
+        phaser.mix_output(x, phased_signal)
+    }
+
+    // largest difference between the control rate and the per-sample output
+    fn max_difference(stages: usize, input: &[f32]) -> f32 {
+        let mut control_rate = phaser(stages);
+        let mut per_sample = phaser(stages);
+        let mut max_difference: f32 = 0.0;
+        for x in input.iter() {
+            let left = control_rate.process_left(*x) - process_per_sample(&mut per_sample, *x, false);
+            let right = control_rate.process_right(*x) - process_per_sample(&mut per_sample, *x, true);
+            max_difference = max_difference.max(left.abs()).max(right.abs());
+        }
+        max_difference
+    }
+
+    // the interpolated coefficients must stay below -80 dB of the per-sample output, from
+    // the first sample on
+    #[test]
+    fn control_rate_matches_per_sample_coefficients() {
+        let input = noise(SAMPLE_RATE as usize);
+        for stages in [MIN_STAGES, 6, 12, MAX_STAGES] {
+            let difference = max_difference(stages, &input);
+            assert!(difference < 1e-4, "{} stages differ by {:.1} dB", stages, 20.0 * difference.log10());
+        }
+    }
+
+    // stages that come back in the middle of a control interval must start from the current
+    // sweep, not from the coefficients they had when they were switched off
+    #[test]
+    fn added_stages_start_on_the_sweep() {
+        let mut phaser = phaser(12);
+        for x in noise(1000) {
+            phaser.process_left(x);
+            phaser.process_right(x);
+        }
+        set_stages(&mut phaser, 4);
+        for x in noise(12000) {
+            phaser.process_left(x);
+            phaser.process_right(x);
+        }
+        set_stages(&mut phaser, 12);
+        assert!(phaser.left_modulation.countdown != 0);
+        let mut max_error: f32 = 0.0;
+        while phaser.left_modulation.countdown > 0 {
+            let targets = phaser.stage_alphas(phaser.lfo.next_value_with_offset(0.0));
+            for (alpha, target) in phaser.left_modulation.alphas[4..12].iter().zip(&targets[4..12]) {
+                max_error = max_error.max((alpha - target).abs());
+            }
+            phaser.process_left(0.0);
+            phaser.process_right(0.0);
+        }
+        assert!(max_error < 0.01, "the added stages are off by {}", max_error);
+    }
+
+    // timing only means something in a release build, so this one runs on its own:
+    // cargo test --release -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_control_rate() {
+        let input = noise(10 * SAMPLE_RATE as usize);
+        for stages in [6, MAX_STAGES] {
+            let mut control_rate = phaser(stages);
+            let start = Instant::now();
+            for x in input.iter() {
+                black_box(control_rate.process_left(black_box(*x)));
+                black_box(control_rate.process_right(black_box(*x)));
+            }
+            let control_rate_time = start.elapsed();
+
+            let mut per_sample = phaser(stages);
+            let start = Instant::now();
+            for x in input.iter() {
+                black_box(process_per_sample(&mut per_sample, black_box(*x), false));
+                black_box(process_per_sample(&mut per_sample, black_box(*x), true));
+            }
+            let per_sample_time = start.elapsed();
+
+            let difference = max_difference(stages, &input);
+            let speedup = per_sample_time.as_secs_f64() / control_rate_time.as_secs_f64();
+            println!(
+                "{} stages, 10 s stereo: per sample {:?}, control rate {:?} ({:.1}x), max difference {:.1} dB",
+                stages,
+                per_sample_time,
+                control_rate_time,
+                speedup,
+                20.0 * difference.log10()
+            );
+            assert!(speedup > 1.5, "{} stages are only {:.1}x faster at control rate", stages, speedup);
+        }
+    }
+}